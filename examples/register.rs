@@ -5,10 +5,13 @@ extern crate olifants;
 extern crate futures;
 extern crate tokio_core;
 
-use futures::{Future, IntoFuture};
+use futures::Future;
+use futures::future::Either;
 use olifants::Client;
 use olifants::api::oauth;
+use olifants::credentials::{CredentialStore, JsonFileStore};
 use olifants::error::*;
+use olifants::loopback;
 use tokio_core::reactor::Core;
 
 // Get from environment variable, or from stdin if variable is absent
@@ -35,57 +38,69 @@ fn get_env(name: &str) -> Result<String> {
     }
 }
 
+fn print_url(url: &str) {
+    println!(
+        "Please visit the following URL to authorize the app:\n{}\n",
+        url
+    );
+    println!("Waiting for the browser to be redirected back...\n");
+}
+
 quick_main!(|| -> Result<()> {
     let mut core = Core::new().chain_err(|| "could not create Core")?;
     let client = Client::new(&core.handle(), "olifants").chain_err(
         || "could not create Client",
     )?;
 
+    let credentials_file =
+        std::env::var("CREDENTIALS_FILE").unwrap_or_else(|_| "credentials.json".to_string());
+    let store = JsonFileStore::new(&credentials_file[..]);
+
     let instance_url = get_env("INSTANCE_URL")?;
-    let scopes = &get_env("CLIENT_SCOPES")?;
+    let scopes = get_env("CLIENT_SCOPES")?;
+    let scopes = oauth::Scopes::from_str(&scopes);
 
-    let app = oauth::App {
-        client_name: &get_env("CLIENT_NAME")?,
-        redirect_uris: oauth::OOB_REDIRECT_URI,
-        scopes: oauth::Scopes::from_str(&scopes),
-        website: &get_env("CLIENT_WEBSITE")?,
-    };
+    let login = match store.app(&instance_url)? {
+        Some(ref stored)
+            if loopback::redirect_port(&stored.app.redirect_uri).is_some() &&
+                stored.covers(&scopes) => {
+            println!("\nUsing app from {}...\n", credentials_file);
 
-    println!("\nRegistering app...\n");
-    let register = client
-        .create_app(&instance_url, &app)
-        .map(|resp| {
-            println!("Created app successfully!");
-            println!("{:#?}\n", resp);
+            let app = stored.app.clone();
+            let token = client
+                .loopback_authorize(&instance_url, &app, &scopes, print_url)
+                .map(move |token| (app, token));
 
-            println!(
-                "Please visit the following URL to obtain an authorization code:\n{}\n",
-                oauth::authorization_url(&instance_url, &resp.client_id, &resp.redirect_uri)
-            );
+            Either::A(token)
+        }
+        _ => {
+            let client_name = get_env("CLIENT_NAME")?;
+            let website = get_env("CLIENT_WEBSITE")?;
 
-            resp
-        })
-        .and_then(|app| {
-            get_env("AUTH_CODE")
-                .map(move |code| (app, code))
-                .into_future()
-        })
-        .and_then(|(app, code)| {
-            println!("\nRequesting access token...");
-            println!("(if this fails, you can run the `token` example to try again)\n");
+            println!("\nRegistering app...\n");
+            let login = client
+                .loopback_login(&store, &instance_url, &client_name, &scopes, &website, print_url)
+                .map(|login| (login.app, login.token));
 
+            Either::B(login)
+        }
+    };
+
+    let register = login
+        .and_then(|(app, token)| {
             client
-                .get_token(
-                    &instance_url,
-                    oauth::OOB_REDIRECT_URI,
-                    &app.client_id,
-                    &app.client_secret,
-                    &code,
-                )
-                .then(|r| r.chain_err(|| "failed to get access token"))
-                .map(|token_resp| {
-                    println!("{:#?}", token_resp);
-                })
+                .verify_credentials(&instance_url, &token.access_token)
+                .map(move |account| (app, token, account))
+        })
+        .and_then(|(app, token, account)| {
+            store.set_token(&instance_url, &account.acct, &token)?;
+
+            println!("Logged in as {} successfully!", account.acct);
+            println!("{:#?}\n", app);
+            println!("{:#?}\n", token);
+            println!("Saved credentials to {}", credentials_file);
+
+            Ok(())
         });
 
     core.run(register).chain_err(|| "request failed")
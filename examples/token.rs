@@ -8,6 +8,7 @@ extern crate tokio_core;
 use futures::Future;
 use olifants::Client;
 use olifants::api::oauth;
+use olifants::credentials::{CredentialStore, JsonFileStore};
 use olifants::error::*;
 use tokio_core::reactor::Core;
 
@@ -41,6 +42,10 @@ quick_main!(|| -> Result<()> {
         || "could not create Client",
     )?;
 
+    let credentials_file =
+        std::env::var("CREDENTIALS_FILE").unwrap_or_else(|_| "credentials.json".to_string());
+    let store = JsonFileStore::new(&credentials_file[..]);
+
     let instance_url = get_env("INSTANCE_URL")?;
     let client_id = get_env("CLIENT_ID")?;
     let client_secret = get_env("CLIENT_SECRET")?;
@@ -48,7 +53,9 @@ quick_main!(|| -> Result<()> {
         if std::env::var("AUTH_CODE").is_err() {
             println!(
                 "\nPlease visit the following URL to obtain an authorization code:\n{}\n",
-                oauth::authorization_url(&instance_url, &client_id, oauth::OOB_REDIRECT_URI)
+                oauth::AuthorizationRequest::new(&client_id, oauth::OOB_REDIRECT_URI)
+                    .pkce(false)
+                    .url(&instance_url)?
             );
         }
         get_env("AUTH_CODE")?
@@ -64,8 +71,18 @@ quick_main!(|| -> Result<()> {
             &client_secret,
             &auth_code,
         )
-        .map(|token_resp| {
-            println!("{:#?}", token_resp);
+        .and_then(|token| {
+            client
+                .verify_credentials(&instance_url, &token.access_token)
+                .map(move |account| (token, account))
+        })
+        .and_then(|(token, account)| {
+            store.set_token(&instance_url, &account.acct, &token)?;
+
+            println!("{:#?}\n", token);
+            println!("Saved token for {} to {}", account.acct, credentials_file);
+
+            Ok(())
         });
 
     core.run(token).chain_err(|| "request failed")
@@ -1,2 +1,4 @@
 pub mod oauth;
+#[macro_use]
 pub mod v1;
+pub mod v2;
@@ -1,4 +1,12 @@
+use base64;
+use error::*;
+use rand::{self, Rng};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
+use std::collections::{HashSet, hash_set};
+use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
 use url;
 
 #[derive(Clone, Debug, Serialize)]
@@ -32,38 +40,225 @@ impl<'a> Scopes<'a> {
             scopes
                 .as_ref()
                 .iter()
-                .map(|s| s.as_param())
+                .map(|s| s.to_string())
                 .collect::<Vec<_>>()
                 .join(" ")
                 .into(),
         )
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(scopes: &'a str) -> Self {
         Scopes(scopes.into())
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Scope {
+/// An OAuth scope, such as `read`, `write:media` or `admin:read:accounts`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Scope {
+    pub category: Category,
+    /// The kind of resource the scope is limited to, or `None` for all of
+    /// them.
+    pub resource: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Category {
     Read,
     Write,
+    /// Deprecated in favour of `read:follows`, `write:follows`, and the
+    /// same for blocks and mutes.
     Follow,
+    Push,
+    /// Only allows reading the user's own profile.
+    Profile,
+    AdminRead,
+    AdminWrite,
 }
 
-impl Scope {
-    pub fn as_param(&self) -> &'static str {
-        use self::Scope::*;
+impl Category {
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Category::Read => "read",
+            Category::Write => "write",
+            Category::Follow => "follow",
+            Category::Push => "push",
+            Category::Profile => "profile",
+            Category::AdminRead => "admin:read",
+            Category::AdminWrite => "admin:write",
+        }
+    }
 
+    fn has_resources(&self) -> bool {
         match *self {
-            Read => "read",
-            Write => "write",
-            Follow => "follow",
+            Category::Read | Category::Write | Category::AdminRead | Category::AdminWrite => true,
+            Category::Follow | Category::Push | Category::Profile => false,
+        }
+    }
+}
+
+impl Scope {
+    pub fn new(category: Category) -> Self {
+        Scope {
+            category,
+            resource: None,
+        }
+    }
+
+    pub fn with_resource<S: Into<String>>(category: Category, resource: S) -> Self {
+        Scope {
+            category,
+            resource: Some(resource.into()),
+        }
+    }
+
+    /// Whether a token with this scope can do everything that `other`
+    /// allows. For example, `read` implies `read:statuses`, and `follow`
+    /// implies `write:blocks`.
+    pub fn implies(&self, other: &Scope) -> bool {
+        if self.category == other.category {
+            return self.resource.is_none() || self.resource == other.resource;
+        }
+
+        match (self.category, other.category) {
+            (Category::Follow, Category::Read) | (Category::Follow, Category::Write) => matches!(
+                other.resource.as_deref(),
+                Some("follows") | Some("blocks") | Some("mutes")
+            ),
+            (Category::Read, Category::Profile) => {
+                matches!(self.resource.as_deref(), None | Some("accounts"))
+            }
+            _ => false,
         }
     }
+
+    #[deprecated(since = "0.2.0", note = "use `to_string` instead")]
+    pub fn as_param(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl From<Category> for Scope {
+    fn from(category: Category) -> Self {
+        Scope::new(category)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.category.as_str())?;
+        if let Some(ref resource) = self.resource {
+            write!(f, ":{}", resource)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (category, rest) = if let Some(rest) = value.strip_prefix("admin:") {
+            let (name, resource) = split_resource(rest);
+            let category = match name {
+                "read" => Category::AdminRead,
+                "write" => Category::AdminWrite,
+                _ => bail!(ErrorKind::Scope(value.to_string())),
+            };
+            (category, resource)
+        } else {
+            let (name, resource) = split_resource(value);
+            let category = match name {
+                "read" => Category::Read,
+                "write" => Category::Write,
+                "follow" => Category::Follow,
+                "push" => Category::Push,
+                "profile" => Category::Profile,
+                _ => bail!(ErrorKind::Scope(value.to_string())),
+            };
+            (category, resource)
+        };
+
+        match rest {
+            Some(resource) if !category.has_resources() || resource.is_empty() => {
+                bail!(ErrorKind::Scope(value.to_string()))
+            }
+            _ => Ok(Scope {
+                category,
+                resource: rest.map(str::to_string),
+            }),
+        }
+    }
+}
+
+fn split_resource(value: &str) -> (&str, Option<&str>) {
+    match value.find(':') {
+        Some(index) => (&value[..index], Some(&value[index + 1..])),
+        None => (value, None),
+    }
+}
+
+/// A set of scopes, such as those granted to a token.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScopeSet(HashSet<Scope>);
+
+impl ScopeSet {
+    /// Parse a space-separated list of scopes. Scopes that this crate
+    /// doesn't know about are skipped.
+    pub fn parse(scopes: &str) -> Self {
+        scopes
+            .split_whitespace()
+            .filter_map(|scope| scope.parse().ok())
+            .collect()
+    }
+
+    pub fn contains(&self, scope: &Scope) -> bool {
+        self.0.contains(scope)
+    }
+
+    /// Whether any scope in the set implies `scope`.
+    pub fn implies(&self, scope: &Scope) -> bool {
+        self.0.iter().any(|s| s.implies(scope))
+    }
+
+    /// Whether every scope in `other` is implied by this set.
+    pub fn implies_all(&self, other: &ScopeSet) -> bool {
+        other.0.iter().all(|scope| self.implies(scope))
+    }
+
+    pub fn insert(&mut self, scope: Scope) -> bool {
+        self.0.insert(scope)
+    }
+
+    pub fn iter(&self) -> hash_set::Iter<'_, Scope> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromIterator<Scope> for ScopeSet {
+    fn from_iter<I: IntoIterator<Item = Scope>>(iter: I) -> Self {
+        ScopeSet(iter.into_iter().collect())
+    }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+impl<'a> IntoIterator for &'a ScopeSet {
+    type Item = &'a Scope;
+    type IntoIter = hash_set::Iter<'a, Scope>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct CreateAppResponse {
     pub id: u32,
     pub redirect_uri: String,
@@ -71,27 +266,128 @@ pub struct CreateAppResponse {
     pub client_secret: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct TokenResponse {
     pub access_token: String,
     pub refresh_token: Option<String>,
     pub token_type: String,
+    /// Lifetime of the access token in seconds, if it expires.
     pub expires_in: Option<u64>,
     pub scope: Option<String>,
+    /// UNIX timestamp of when the token was issued.
+    pub created_at: Option<i64>,
 }
 
-pub const OOB_REDIRECT_URI: &'static str = "urn:ietf:wg:oauth:2.0:oob";
+impl TokenResponse {
+    /// The scopes granted to the token. Servers that leave `scope` out
+    /// grant `read`.
+    pub fn scopes(&self) -> ScopeSet {
+        ScopeSet::parse(self.scope.as_deref().unwrap_or("read"))
+    }
+}
 
-pub fn authorization_url(instance_url: &str, client_id: &str, redirect_uri: &str) -> String {
-    format!(
-        "{}/oauth/authorize\
-        ?client_id={}\
-        &response_type=code\
-        &redirect_uri={}",
-        instance_url,
-        client_id,
-        redirect_uri
-    )
+pub const OOB_REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+
+/// Parameters for the `/oauth/authorize` page that the user is sent to.
+///
+/// A random `state` and PKCE code verifier are generated for each request.
+/// Keep the request around until the user is redirected back, then pass it
+/// to `Client::get_token_pkce` to check the state and send the verifier.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AuthorizationRequest {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: String,
+    /// `None` if PKCE is disabled.
+    pub code_verifier: Option<String>,
+    pub force_login: bool,
+}
+
+impl AuthorizationRequest {
+    pub fn new<C, R>(client_id: C, redirect_uri: R) -> Self
+    where
+        C: Into<String>,
+        R: Into<String>,
+    {
+        AuthorizationRequest {
+            client_id: client_id.into(),
+            redirect_uri: redirect_uri.into(),
+            scope: None,
+            state: random_string(32),
+            code_verifier: Some(random_string(64)),
+            force_login: false,
+        }
+    }
+
+    /// Request a subset of the scopes the app was registered with. Defaults
+    /// to `read` if not set.
+    pub fn scopes(mut self, scopes: &Scopes) -> Self {
+        self.scope = Some(scopes.0.to_string());
+        self
+    }
+
+    pub fn state<S: Into<String>>(mut self, state: S) -> Self {
+        self.state = state.into();
+        self
+    }
+
+    /// Turn PKCE on or off. It is on by default, and servers that don't
+    /// support it ignore it.
+    pub fn pkce(mut self, pkce: bool) -> Self {
+        self.code_verifier = if pkce { Some(random_string(64)) } else { None };
+        self
+    }
+
+    /// Ask the user to log in again, even if they already have a session,
+    /// so they can choose which account to authorize.
+    pub fn force_login(mut self, force_login: bool) -> Self {
+        self.force_login = force_login;
+        self
+    }
+
+    /// The S256 code challenge for the verifier.
+    pub fn code_challenge(&self) -> Option<String> {
+        self.code_verifier.as_ref().map(|verifier| {
+            base64::encode_config(
+                &Sha256::digest(verifier.as_bytes()),
+                base64::URL_SAFE_NO_PAD,
+            )
+        })
+    }
+
+    pub fn url(&self, instance_url: &str) -> Result<String> {
+        let base_url = format!("{}/oauth/authorize", instance_url);
+        let mut url = url::Url::parse(&base_url).chain_err(|| ErrorKind::Uri(base_url.to_string()))?;
+
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("client_id", &self.client_id)
+                .append_pair("response_type", "code")
+                .append_pair("redirect_uri", &self.redirect_uri);
+
+            if let Some(ref scope) = self.scope {
+                query.append_pair("scope", scope);
+            }
+            query.append_pair("state", &self.state);
+            if let Some(challenge) = self.code_challenge() {
+                query
+                    .append_pair("code_challenge", &challenge)
+                    .append_pair("code_challenge_method", "S256");
+            }
+            if self.force_login {
+                query.append_pair("force_login", "true");
+            }
+        }
+
+        Ok(url.into_string())
+    }
+}
+
+// Alphanumerics are all unreserved, so these need no encoding
+fn random_string(len: usize) -> String {
+    rand::thread_rng().gen_ascii_chars().take(len).collect()
 }
 
 #[cfg(test)]
@@ -101,7 +397,11 @@ mod test {
 
     #[test]
     fn scopes_constructor() {
-        let scopes1 = Scopes::new([Scope::Read, Scope::Write, Scope::Follow]);
+        let scopes1 = Scopes::new([
+            Scope::new(Category::Read),
+            Scope::new(Category::Write),
+            Scope::new(Category::Follow),
+        ]);
         assert_eq!(scopes1.0, "read write follow");
 
         let scopes2 = Scopes::from_str("whatever");
@@ -125,4 +425,91 @@ mod test {
             &website=https%3A%2F%2Fexample.com"
         );
     }
+
+    #[test]
+    fn authorization_url() {
+        let mut request = AuthorizationRequest::new("abc", "http://127.0.0.1:8080/callback?a=b")
+            .scopes(&Scopes::new([Category::Read.into(), Category::Write.into()]))
+            .state("xyz")
+            .force_login(true);
+        request.code_verifier = Some("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+
+        assert_eq!(
+            request.url("https://example.com").unwrap(),
+            "https://example.com/oauth/authorize\
+            ?client_id=abc\
+            &response_type=code\
+            &redirect_uri=http%3A%2F%2F127.0.0.1%3A8080%2Fcallback%3Fa%3Db\
+            &scope=read+write\
+            &state=xyz\
+            &code_challenge=E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM\
+            &code_challenge_method=S256\
+            &force_login=true"
+        );
+
+        let request = request.pkce(false).force_login(false);
+        assert_eq!(
+            request.url("https://example.com").unwrap(),
+            "https://example.com/oauth/authorize\
+            ?client_id=abc\
+            &response_type=code\
+            &redirect_uri=http%3A%2F%2F127.0.0.1%3A8080%2Fcallback%3Fa%3Db\
+            &scope=read+write\
+            &state=xyz"
+        );
+    }
+
+    #[test]
+    fn authorization_request_is_random() {
+        let a = AuthorizationRequest::new("abc", OOB_REDIRECT_URI);
+        let b = AuthorizationRequest::new("abc", OOB_REDIRECT_URI);
+
+        assert_ne!(a.state, b.state);
+        assert_ne!(a.code_verifier, b.code_verifier);
+        assert_eq!(a.code_verifier.unwrap().len(), 64);
+    }
+
+    #[test]
+    fn parse_scopes() {
+        let scope = |s: &str| s.parse::<Scope>().unwrap();
+
+        assert_eq!(scope("read"), Scope::new(Category::Read));
+        assert_eq!(scope("write:media"), Scope::with_resource(Category::Write, "media"));
+        assert_eq!(
+            scope("admin:read:accounts"),
+            Scope::with_resource(Category::AdminRead, "accounts")
+        );
+        assert_eq!(scope("admin:write"), Scope::new(Category::AdminWrite));
+        assert_eq!(scope("push"), Scope::new(Category::Push));
+        assert_eq!(scope("admin:read:accounts").to_string(), "admin:read:accounts");
+
+        assert!("crypto".parse::<Scope>().is_err());
+        assert!("push:all".parse::<Scope>().is_err());
+        assert!("read:".parse::<Scope>().is_err());
+        assert!("admin:follow".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn scope_implication() {
+        let scope = |s: &str| s.parse::<Scope>().unwrap();
+
+        assert!(scope("read").implies(&scope("read:notifications")));
+        assert!(scope("read:notifications").implies(&scope("read:notifications")));
+        assert!(!scope("read:statuses").implies(&scope("read:notifications")));
+        assert!(!scope("read:statuses").implies(&scope("read")));
+        assert!(!scope("read").implies(&scope("write:statuses")));
+        assert!(!scope("read").implies(&scope("admin:read:accounts")));
+        assert!(scope("follow").implies(&scope("write:blocks")));
+        assert!(!scope("follow").implies(&scope("write:statuses")));
+        assert!(scope("read:accounts").implies(&scope("profile")));
+
+        let granted = ScopeSet::parse("read:notifications write follow crypto");
+        assert_eq!(granted.len(), 3);
+        assert!(granted.implies(&scope("read:notifications")));
+        assert!(granted.implies(&scope("write:media")));
+        assert!(granted.implies(&scope("read:mutes")));
+        assert!(!granted.implies(&scope("read:statuses")));
+        assert!(granted.implies_all(&ScopeSet::parse("write:favourites read:follows")));
+        assert!(!granted.implies_all(&ScopeSet::parse("write read")));
+    }
 }
@@ -1,10 +1,81 @@
+use bool_str;
 use chrono;
+use error::*;
+use multipart;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use url;
+
+/// Defines an enum that (de)serializes as a string, with an `Other` variant
+/// for values this crate doesn't know about.
+macro_rules! string_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident { $($(#[$variant_meta:meta])* $variant:ident => $value:expr,)* }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            Other(String),
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match *self {
+                    $($name::$variant => $value,)*
+                    $name::Other(ref value) => value,
+                }
+            }
+        }
+
+        impl<'a> From<&'a str> for $name {
+            fn from(value: &'a str) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    other => $name::Other(other.to_string()),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl ::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                let value = String::deserialize(deserializer)?;
+                Ok($name::from(value.as_str()))
+            }
+        }
+    }
+}
 
 pub type DateTime = chrono::DateTime<chrono::Utc>;
 pub type StatusId = String;
 pub type AccountId = String;
 pub type MentionId = String;
 pub type AttachmentId = String;
+pub type NotificationId = String;
+pub type ConversationId = String;
+pub type AnnouncementId = String;
+pub type EncryptedMessageId = String;
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Status {
@@ -21,9 +92,12 @@ pub struct Status {
     pub favourites_count: i32,
     pub reblogged: Option<bool>,
     pub favourited: Option<bool>,
+    pub bookmarked: Option<bool>,
+    pub pinned: Option<bool>,
+    pub muted: Option<bool>,
     pub sensitive: Option<bool>,
     pub spoiler_text: String,
-    pub visibility: String, // TODO: Enum values -- direct, private, unlisted, public
+    pub visibility: Visibility,
     pub media_attachments: Vec<Attachment>,
     pub mentions: Vec<Mention>,
     pub tags: Vec<Tag>,
@@ -31,6 +105,50 @@ pub struct Status {
     pub language: Option<String>,
 }
 
+string_enum! {
+    pub enum Visibility {
+        Public => "public",
+        Unlisted => "unlisted",
+        Private => "private",
+        Direct => "direct",
+    }
+}
+
+string_enum! {
+    #[derive(Default)]
+    pub enum MediaType {
+        Image => "image",
+        Video => "video",
+        Gifv => "gifv",
+        Audio => "audio",
+        #[default]
+        Unknown => "unknown",
+    }
+}
+
+string_enum! {
+    pub enum NotificationType {
+        Mention => "mention",
+        Status => "status",
+        Reblog => "reblog",
+        Follow => "follow",
+        FollowRequest => "follow_request",
+        Favourite => "favourite",
+        Poll => "poll",
+        Update => "update",
+        AdminSignUp => "admin.sign_up",
+        AdminReport => "admin.report",
+    }
+}
+
+/// The statuses above and below a status in its thread, each in
+/// chronological order.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Context {
+    pub ancestors: Vec<Status>,
+    pub descendants: Vec<Status>,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Mention {
     pub id: MentionId,
@@ -43,6 +161,18 @@ pub struct Mention {
 pub struct Tag {
     pub name: String,
     pub url: String,
+    /// Daily usage for the past week, most recent first.
+    #[serde(default)]
+    pub history: Vec<TagHistory>,
+}
+
+/// Usage of a hashtag on one day. The API sends the counts as strings.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TagHistory {
+    /// UNIX timestamp of midnight on the day.
+    pub day: String,
+    pub uses: String,
+    pub accounts: String,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
@@ -68,25 +198,609 @@ pub struct Account {
     pub avatar_static: String,
     pub header: String,
     pub header_static: String,
+    #[serde(default)]
+    pub bot: bool,
+    pub moved: Option<Box<Account>>,
+    #[serde(default)]
+    pub fields: Vec<Field>,
+    #[serde(default)]
+    pub emojis: Vec<Emoji>,
+    /// Only present when fetching the current user's own account.
+    pub source: Option<Source>,
+    pub last_status_at: Option<String>, // Date only (e.g. "2019-11-24") in newer versions
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Relationship {
+    pub id: AccountId,
+    pub following: bool,
+    pub followed_by: bool,
+    pub blocking: bool,
+    pub muting: bool,
+    pub requested: bool,
+    #[serde(default)]
+    pub showing_reblogs: bool,
+    #[serde(default)]
+    pub notifying: bool,
+    #[serde(default)]
+    pub blocked_by: bool,
+    #[serde(default)]
+    pub muting_notifications: bool,
+    #[serde(default)]
+    pub domain_blocking: bool,
+    #[serde(default)]
+    pub endorsed: bool,
+    pub note: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+    pub verified_at: Option<DateTime>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Source {
+    pub note: String,
+    #[serde(default)]
+    pub fields: Vec<Field>,
+    pub privacy: Option<Visibility>,
+    pub sensitive: Option<bool>,
+    pub language: Option<String>,
+    pub follow_requests_count: Option<i32>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Attachment {
     pub id: AttachmentId,
     #[serde(rename = "type")]
-    pub media_type: String, // TODO: Enum values -- image, video, gifv
-    pub url: String,
+    pub media_type: MediaType,
+    pub url: Option<String>, // Absent while the media is still being processed
     pub remote_url: Option<String>,
     pub preview_url: String,
-    pub text_url: Option<String>, // TODO: Add meta (dimensions, etc)
+    pub text_url: Option<String>,
+    pub meta: Option<AttachmentMeta>,
+    pub description: Option<String>,
+    pub blurhash: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct AttachmentMeta {
+    pub original: Option<MediaDimensions>,
+    pub small: Option<MediaDimensions>,
+    pub focus: Option<Focus>,
+    pub length: Option<String>,
+    pub duration: Option<f64>,
+    pub fps: Option<f64>,
+    pub aspect: Option<f64>,
+    pub audio_encode: Option<String>,
+    pub audio_bitrate: Option<String>,
+    pub audio_channels: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct MediaDimensions {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size: Option<String>,
+    pub aspect: Option<f64>,
+    pub duration: Option<f64>,
+    pub frame_rate: Option<String>,
+    pub bitrate: Option<u64>,
+}
+
+/// Focal point of an image, with `x` and `y` between -1.0 and 1.0, where
+/// (0, 0) is the center.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Focus {
+    pub x: f64,
+    pub y: f64,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Notification {
-    pub id: i64,
+    pub id: NotificationId,
     #[serde(rename = "type")]
-    pub notification_type: String, // TODO: Enum values -- mention, reblog, favourite, follow
+    pub notification_type: NotificationType,
     pub created_at: DateTime,
     pub account: Account,
     pub status: Option<Status>,
 }
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Emoji {
+    pub shortcode: String,
+    pub url: String,
+    pub static_url: String,
+    #[serde(default)]
+    pub visible_in_picker: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Conversation {
+    pub id: ConversationId,
+    pub accounts: Vec<Account>,
+    pub last_status: Option<Status>,
+    pub unread: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Announcement {
+    pub id: AnnouncementId,
+    pub content: String,
+    pub starts_at: Option<DateTime>,
+    pub ends_at: Option<DateTime>,
+    pub all_day: bool,
+    pub published_at: DateTime,
+    pub updated_at: DateTime,
+    pub read: Option<bool>,
+    #[serde(default)]
+    pub mentions: Vec<Mention>,
+    #[serde(default)]
+    pub statuses: Vec<AnnouncementStatus>,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub emojis: Vec<Emoji>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct AnnouncementStatus {
+    pub id: StatusId,
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Reaction {
+    pub name: String,
+    pub count: i32,
+    pub me: Option<bool>,
+    pub url: Option<String>,
+    pub static_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct AnnouncementReaction {
+    pub name: String,
+    pub count: i32,
+    pub announcement_id: AnnouncementId,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EncryptedMessage {
+    pub id: EncryptedMessageId,
+    pub account_id: AccountId,
+    pub device_id: String,
+    #[serde(rename = "type")]
+    pub message_type: i32,
+    pub body: String,
+    pub digest: String,
+    pub message_franking: String,
+    pub created_at: DateTime,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Instance {
+    pub uri: String,
+    pub title: String,
+    #[serde(default)]
+    pub short_description: String,
+    pub description: String,
+    pub email: String,
+    pub version: String,
+    pub urls: InstanceUrls,
+    pub stats: InstanceStats,
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    #[serde(default)]
+    pub registrations: bool,
+    #[serde(default)]
+    pub approval_required: bool,
+    #[serde(default)]
+    pub invites_enabled: bool,
+    /// Only sent by Mastodon 3.4.2 and later.
+    pub configuration: Option<InstanceConfiguration>,
+    pub contact_account: Option<Account>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct InstanceUrls {
+    pub streaming_api: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct InstanceStats {
+    pub user_count: u64,
+    pub status_count: u64,
+    pub domain_count: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct InstanceConfiguration {
+    pub statuses: StatusConfiguration,
+    pub media_attachments: MediaConfiguration,
+    pub polls: PollConfiguration,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct StatusConfiguration {
+    pub max_characters: u32,
+    pub max_media_attachments: u32,
+    /// Length that every URL counts as, regardless of its actual length.
+    pub characters_reserved_per_url: u32,
+}
+
+/// Upload limits. Sizes are in bytes, and matrix limits are in pixels
+/// (width times height).
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct MediaConfiguration {
+    pub supported_mime_types: Vec<String>,
+    pub image_size_limit: u64,
+    pub image_matrix_limit: u64,
+    pub video_size_limit: u64,
+    pub video_frame_rate_limit: u32,
+    pub video_matrix_limit: u64,
+}
+
+/// Poll limits. Expirations are in seconds.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct PollConfiguration {
+    pub max_options: u32,
+    pub max_characters_per_option: u32,
+    pub min_expiration: u64,
+    pub max_expiration: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NewStatus<'a> {
+    pub status: &'a str,
+    pub in_reply_to_id: Option<&'a str>,
+    pub media_ids: Vec<&'a str>,
+    pub sensitive: Option<bool>,
+    pub spoiler_text: Option<&'a str>,
+    pub visibility: Option<Visibility>,
+    pub language: Option<&'a str>,
+}
+
+impl<'a> NewStatus<'a> {
+    pub fn new(status: &'a str) -> Self {
+        NewStatus {
+            status,
+            ..NewStatus::default()
+        }
+    }
+
+    pub fn in_reply_to_id(mut self, id: &'a str) -> Self {
+        self.in_reply_to_id = Some(id);
+        self
+    }
+
+    pub fn media_id(mut self, id: &'a str) -> Self {
+        self.media_ids.push(id);
+        self
+    }
+
+    pub fn sensitive(mut self, sensitive: bool) -> Self {
+        self.sensitive = Some(sensitive);
+        self
+    }
+
+    pub fn spoiler_text(mut self, spoiler_text: &'a str) -> Self {
+        self.spoiler_text = Some(spoiler_text);
+        self
+    }
+
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = Some(visibility);
+        self
+    }
+
+    pub fn language(mut self, language: &'a str) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    pub fn as_form_urlencoded(&self) -> String {
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        form.append_pair("status", self.status);
+
+        if let Some(id) = self.in_reply_to_id {
+            form.append_pair("in_reply_to_id", id);
+        }
+        for id in &self.media_ids {
+            form.append_pair("media_ids[]", id);
+        }
+        if let Some(sensitive) = self.sensitive {
+            form.append_pair("sensitive", bool_str(sensitive));
+        }
+        if let Some(spoiler_text) = self.spoiler_text {
+            form.append_pair("spoiler_text", spoiler_text);
+        }
+        if let Some(ref visibility) = self.visibility {
+            form.append_pair("visibility", visibility.as_str());
+        }
+        if let Some(language) = self.language {
+            form.append_pair("language", language);
+        }
+
+        form.finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewMedia<'a> {
+    pub data: Vec<u8>,
+    pub file_name: String,
+    pub mime_type: &'a str,
+    pub description: Option<&'a str>,
+    pub focus: Option<(f32, f32)>,
+}
+
+impl<'a> NewMedia<'a> {
+    pub fn new<S>(data: Vec<u8>, file_name: S, mime_type: &'a str) -> Self
+    where
+        S: Into<String>,
+    {
+        NewMedia {
+            data,
+            file_name: file_name.into(),
+            mime_type,
+            description: None,
+            focus: None,
+        }
+    }
+
+    /// Read the media from a file. This blocks until the file has been read.
+    pub fn from_path<P>(path: P, mime_type: &'a str) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut data = Vec::new();
+
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .chain_err(|| ErrorKind::File(path.display().to_string()))?;
+
+        let file_name = path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "file".to_string());
+
+        Ok(NewMedia::new(data, file_name, mime_type))
+    }
+
+    pub fn description(mut self, description: &'a str) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    /// Focal point for cropping thumbnails, with `x` and `y` between -1.0 and
+    /// 1.0, where (0, 0) is the center of the image.
+    pub fn focus(mut self, x: f32, y: f32) -> Self {
+        self.focus = Some((x, y));
+        self
+    }
+
+    pub fn as_form(&self) -> multipart::Form {
+        let mut form =
+            multipart::Form::new().file("file", &self.file_name, self.mime_type, &self.data);
+
+        if let Some(description) = self.description {
+            form = form.text("description", description);
+        }
+        if let Some((x, y)) = self.focus {
+            form = form.text("focus", &format!("{},{}", x, y));
+        }
+
+        form
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpdateCredentials<'a> {
+    pub display_name: Option<&'a str>,
+    pub note: Option<&'a str>,
+    pub avatar: Option<NewMedia<'a>>,
+    pub header: Option<NewMedia<'a>>,
+    pub locked: Option<bool>,
+    pub bot: Option<bool>,
+    pub discoverable: Option<bool>,
+    pub fields: Option<Vec<(&'a str, &'a str)>>,
+}
+
+impl<'a> UpdateCredentials<'a> {
+    pub fn new() -> Self {
+        UpdateCredentials::default()
+    }
+
+    pub fn display_name(mut self, display_name: &'a str) -> Self {
+        self.display_name = Some(display_name);
+        self
+    }
+
+    pub fn note(mut self, note: &'a str) -> Self {
+        self.note = Some(note);
+        self
+    }
+
+    pub fn avatar(mut self, avatar: NewMedia<'a>) -> Self {
+        self.avatar = Some(avatar);
+        self
+    }
+
+    pub fn header(mut self, header: NewMedia<'a>) -> Self {
+        self.header = Some(header);
+        self
+    }
+
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.locked = Some(locked);
+        self
+    }
+
+    pub fn bot(mut self, bot: bool) -> Self {
+        self.bot = Some(bot);
+        self
+    }
+
+    pub fn discoverable(mut self, discoverable: bool) -> Self {
+        self.discoverable = Some(discoverable);
+        self
+    }
+
+    /// Profile metadata, as name/value pairs. This replaces all existing
+    /// fields on the profile.
+    pub fn fields(mut self, fields: Vec<(&'a str, &'a str)>) -> Self {
+        self.fields = Some(fields);
+        self
+    }
+
+    pub fn as_form(&self) -> multipart::Form {
+        let mut form = multipart::Form::new();
+
+        if let Some(display_name) = self.display_name {
+            form = form.text("display_name", display_name);
+        }
+        if let Some(note) = self.note {
+            form = form.text("note", note);
+        }
+        if let Some(ref avatar) = self.avatar {
+            form = form.file("avatar", &avatar.file_name, avatar.mime_type, &avatar.data);
+        }
+        if let Some(ref header) = self.header {
+            form = form.file("header", &header.file_name, header.mime_type, &header.data);
+        }
+        if let Some(locked) = self.locked {
+            form = form.text("locked", bool_str(locked));
+        }
+        if let Some(bot) = self.bot {
+            form = form.text("bot", bool_str(bot));
+        }
+        if let Some(discoverable) = self.discoverable {
+            form = form.text("discoverable", bool_str(discoverable));
+        }
+        if let Some(ref fields) = self.fields {
+            for (i, &(name, value)) in fields.iter().enumerate() {
+                form = form
+                    .text(&format!("fields_attributes[{}][name]", i), name)
+                    .text(&format!("fields_attributes[{}][value]", i), value);
+            }
+        }
+
+        form
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn new_status_as_form_urlencoded() {
+        let status = NewStatus::new("Hello world!")
+            .in_reply_to_id("123")
+            .media_id("1")
+            .media_id("2")
+            .sensitive(true)
+            .spoiler_text("cw")
+            .visibility(Visibility::Unlisted)
+            .language("en");
+
+        assert_eq!(
+            status.as_form_urlencoded(),
+            "status=Hello+world%21\
+            &in_reply_to_id=123\
+            &media_ids%5B%5D=1\
+            &media_ids%5B%5D=2\
+            &sensitive=true\
+            &spoiler_text=cw\
+            &visibility=unlisted\
+            &language=en"
+        );
+
+        assert_eq!(NewStatus::new("hi").as_form_urlencoded(), "status=hi");
+    }
+
+    #[test]
+    fn update_credentials_as_form() {
+        let body = UpdateCredentials::new()
+            .display_name("Bot")
+            .bot(true)
+            .fields(vec![("Website", "https://example.com")])
+            .as_form()
+            .into_body();
+        let body = String::from_utf8(body).unwrap();
+
+        assert!(body.contains("name=\"display_name\"\r\n\r\nBot\r\n"));
+        assert!(body.contains("name=\"bot\"\r\n\r\ntrue\r\n"));
+        assert!(body.contains(
+            "name=\"fields_attributes[0][value]\"\r\n\r\nhttps://example.com\r\n",
+        ));
+        assert!(!body.contains("name=\"note\""));
+    }
+
+    #[test]
+    fn attachment_meta() {
+        use serde_json;
+
+        let attachment: Attachment = serde_json::from_str(
+            r#"{
+                "id": "1",
+                "type": "video",
+                "url": "https://example.com/video.mp4",
+                "remote_url": null,
+                "preview_url": "https://example.com/preview.png",
+                "text_url": null,
+                "meta": {
+                    "length": "0:00:05.00",
+                    "duration": 5.0,
+                    "fps": 30,
+                    "original": {
+                        "width": 640,
+                        "height": 480,
+                        "frame_rate": "30/1",
+                        "duration": 5.0,
+                        "bitrate": 1000000
+                    },
+                    "small": {"width": 400, "height": 300, "size": "400x300", "aspect": 1.3333},
+                    "focus": {"x": -0.5, "y": 0.25}
+                },
+                "description": "A video",
+                "blurhash": "LEHV6nWB2yk8pyo0adR*.7kCMdnj"
+            }"#,
+        ).unwrap();
+
+        let meta = attachment.meta.unwrap();
+        assert_eq!(meta.fps, Some(30.0));
+        assert_eq!(meta.original.unwrap().bitrate, Some(1000000));
+        assert_eq!(meta.small.unwrap().size, Some("400x300".to_string()));
+        assert_eq!(meta.focus, Some(Focus { x: -0.5, y: 0.25 }));
+    }
+
+    #[test]
+    fn string_enums() {
+        use serde_json;
+
+        let known: Visibility = serde_json::from_str("\"unlisted\"").unwrap();
+        assert_eq!(known, Visibility::Unlisted);
+        assert_eq!(serde_json::to_string(&known).unwrap(), "\"unlisted\"");
+
+        let other: NotificationType = serde_json::from_str("\"emoji_reaction\"").unwrap();
+        assert_eq!(other, NotificationType::Other("emoji_reaction".to_string()));
+        assert_eq!(serde_json::to_string(&other).unwrap(), "\"emoji_reaction\"");
+
+        assert_eq!(
+            NotificationType::from("admin.sign_up"),
+            NotificationType::AdminSignUp
+        );
+        assert_eq!(MediaType::Gifv.to_string(), "gifv");
+    }
+}
@@ -0,0 +1,196 @@
+use api::v1::{
+    Account,
+    MediaConfiguration,
+    PollConfiguration,
+    Status,
+    StatusConfiguration,
+    Tag,
+};
+use bool_str;
+use std::fmt;
+use url;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SearchResults {
+    pub accounts: Vec<Account>,
+    pub statuses: Vec<Status>,
+    pub hashtags: Vec<Tag>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Instance {
+    pub domain: String,
+    pub title: String,
+    pub version: String,
+    #[serde(default)]
+    pub source_url: String,
+    pub description: String,
+    pub usage: InstanceUsage,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    pub configuration: InstanceConfiguration,
+    pub registrations: Registrations,
+    pub contact: Contact,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct InstanceUsage {
+    pub users: UserUsage,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct UserUsage {
+    /// Users active in the past 30 days.
+    pub active_month: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct InstanceConfiguration {
+    pub urls: ConfigurationUrls,
+    pub statuses: StatusConfiguration,
+    pub media_attachments: MediaConfiguration,
+    pub polls: PollConfiguration,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ConfigurationUrls {
+    pub streaming: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Registrations {
+    pub enabled: bool,
+    pub approval_required: bool,
+    /// Shown to people who try to sign up while registrations are closed.
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Contact {
+    pub email: String,
+    pub account: Option<Account>,
+}
+
+string_enum! {
+    pub enum SearchType {
+        Accounts => "accounts",
+        Hashtags => "hashtags",
+        Statuses => "statuses",
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchOptions<'a> {
+    pub search_type: Option<SearchType>,
+    pub resolve: Option<bool>,
+    pub following: Option<bool>,
+    pub account_id: Option<&'a str>,
+    pub exclude_unreviewed: Option<bool>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl<'a> SearchOptions<'a> {
+    pub fn new() -> Self {
+        SearchOptions::default()
+    }
+
+    /// Only return results of one type.
+    pub fn search_type(mut self, search_type: SearchType) -> Self {
+        self.search_type = Some(search_type);
+        self
+    }
+
+    /// Look up remote accounts and statuses by `@user@domain` or URL, so they
+    /// are returned with local IDs. Requires an access token.
+    pub fn resolve(mut self, resolve: bool) -> Self {
+        self.resolve = Some(resolve);
+        self
+    }
+
+    /// Only return accounts the user follows.
+    pub fn following(mut self, following: bool) -> Self {
+        self.following = Some(following);
+        self
+    }
+
+    /// Only return statuses posted by this account.
+    pub fn account_id(mut self, account_id: &'a str) -> Self {
+        self.account_id = Some(account_id);
+        self
+    }
+
+    /// Leave out hashtags that haven't been reviewed by moderators.
+    pub fn exclude_unreviewed(mut self, exclude_unreviewed: bool) -> Self {
+        self.exclude_unreviewed = Some(exclude_unreviewed);
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skip this many results. Only takes effect with `search_type`.
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn append_pairs<T>(&self, query: &mut url::form_urlencoded::Serializer<T>)
+    where
+        T: url::form_urlencoded::Target,
+    {
+        if let Some(ref search_type) = self.search_type {
+            query.append_pair("type", search_type.as_str());
+        }
+        if let Some(resolve) = self.resolve {
+            query.append_pair("resolve", bool_str(resolve));
+        }
+        if let Some(following) = self.following {
+            query.append_pair("following", bool_str(following));
+        }
+        if let Some(account_id) = self.account_id {
+            query.append_pair("account_id", account_id);
+        }
+        if let Some(exclude_unreviewed) = self.exclude_unreviewed {
+            query.append_pair("exclude_unreviewed", bool_str(exclude_unreviewed));
+        }
+        if let Some(limit) = self.limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+        if let Some(offset) = self.offset {
+            query.append_pair("offset", &offset.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn search_options_append_pairs() {
+        let options = SearchOptions::new()
+            .search_type(SearchType::Statuses)
+            .resolve(true)
+            .account_id("42")
+            .limit(5)
+            .offset(10);
+
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("q", "@user@example.com");
+        options.append_pairs(&mut query);
+
+        assert_eq!(
+            query.finish(),
+            "q=%40user%40example.com\
+            &type=statuses\
+            &resolve=true\
+            &account_id=42\
+            &limit=5\
+            &offset=10"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Decoder for [BlurHash](https://blurha.sh) strings, as found in
+//! `Attachment::blurhash`, for rendering placeholder images.
+
+use error::*;
+use std::f32::consts::PI;
+
+const CHARACTERS: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Decode a BlurHash into a `width` by `height` image, as RGB bytes in
+/// row-major order. `punch` adjusts the contrast, and is usually 1.0.
+pub fn decode(hash: &str, width: u32, height: u32, punch: f32) -> Result<Vec<u8>> {
+    if hash.len() < 6 {
+        bail!(ErrorKind::Blurhash(hash.to_string()));
+    }
+
+    let size_flag = decode83(hash, 0, 1)?;
+    let num_x = (size_flag % 9) + 1;
+    let num_y = (size_flag / 9) + 1;
+
+    if hash.len() != 4 + 2 * (num_x * num_y) as usize {
+        bail!(ErrorKind::Blurhash(hash.to_string()));
+    }
+
+    let quantised_max = decode83(hash, 1, 2)?;
+    let max_value = (quantised_max + 1) as f32 / 166.0 * punch;
+
+    let mut colors = Vec::with_capacity((num_x * num_y) as usize);
+    colors.push(decode_dc(decode83(hash, 2, 6)?));
+
+    for i in 1..(num_x * num_y) as usize {
+        let value = decode83(hash, 4 + i * 2, 6 + i * 2)?;
+        colors.push(decode_ac(value, max_value));
+    }
+
+    let size = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|size| size.checked_mul(3))
+        .ok_or_else(|| ErrorKind::BlurhashSize(width, height))?;
+    let mut pixels = Vec::with_capacity(size);
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = [0.0f32; 3];
+
+            for j in 0..num_y {
+                for i in 0..num_x {
+                    let basis = (PI * x as f32 * i as f32 / width as f32).cos() *
+                        (PI * y as f32 * j as f32 / height as f32).cos();
+                    let color = colors[(i + j * num_x) as usize];
+
+                    pixel[0] += color[0] * basis;
+                    pixel[1] += color[1] * basis;
+                    pixel[2] += color[2] * basis;
+                }
+            }
+
+            pixels.push(linear_to_srgb(pixel[0]));
+            pixels.push(linear_to_srgb(pixel[1]));
+            pixels.push(linear_to_srgb(pixel[2]));
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn decode83(hash: &str, start: usize, end: usize) -> Result<u32> {
+    hash.as_bytes()[start..end].iter().try_fold(0, |value, c| {
+        match CHARACTERS.iter().position(|d| d == c) {
+            Some(digit) => Ok(value * 83 + digit as u32),
+            None => Err(ErrorKind::Blurhash(hash.to_string()).into()),
+        }
+    })
+}
+
+fn decode_dc(value: u32) -> [f32; 3] {
+    [
+        srgb_to_linear(value >> 16),
+        srgb_to_linear((value >> 8) & 255),
+        srgb_to_linear(value & 255),
+    ]
+}
+
+fn decode_ac(value: u32, max_value: f32) -> [f32; 3] {
+    let quantised = [value / (19 * 19), (value / 19) % 19, value % 19];
+    let unquantise = |q: u32| sign_pow((q as f32 - 9.0) / 9.0, 2.0) * max_value;
+
+    [
+        unquantise(quantised[0]),
+        unquantise(quantised[1]),
+        unquantise(quantised[2]),
+    ]
+}
+
+fn sign_pow(value: f32, exp: f32) -> f32 {
+    value.abs().powf(exp).copysign(value)
+}
+
+fn srgb_to_linear(value: u32) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    if v <= 0.003_130_8 {
+        (v * 12.92 * 255.0 + 0.5) as u8
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_solid_color() {
+        // One component, with a DC value of 0xFF0000
+        let pixels = decode("00TI:j", 2, 2, 1.0).unwrap();
+        assert_eq!(pixels, vec![255, 0, 0, 255, 0, 0, 255, 0, 0, 255, 0, 0]);
+    }
+
+    #[test]
+    fn decode_example() {
+        let pixels = decode("LEHV6nWB2yk8pyo0adR*.7kCMdnj", 32, 24, 1.0).unwrap();
+        assert_eq!(pixels.len(), 32 * 24 * 3);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert!(decode("LEHV6n", 4, 4, 1.0).is_err());
+        assert!(decode("00TI:\"", 4, 4, 1.0).is_err());
+        assert!(decode("00TI:j", u32::MAX, u32::MAX, 1.0).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! The default connector for `Client`, which speaks plain HTTP or HTTPS
+//! depending on the scheme of the URL.
+
+use error::*;
+use futures::{Future, Poll, future};
+use hyper::Uri;
+use hyper::client::{HttpConnector, Service};
+use native_tls;
+use std::fmt;
+use std::io::{self, Read, Write};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_tls::{TlsConnector, TlsStream};
+
+#[derive(Clone)]
+pub struct HttpsConnector {
+    http: HttpConnector,
+    tls: TlsConnector,
+}
+
+impl HttpsConnector {
+    /// Create a connector that resolves host names on a pool of `threads`
+    /// threads.
+    pub fn new(threads: usize, handle: &Handle) -> Result<Self> {
+        let mut http = HttpConnector::new(threads, handle);
+        http.enforce_http(false);
+
+        let tls = native_tls::TlsConnector::new().chain_err(|| ErrorKind::Initialization)?;
+
+        Ok(HttpsConnector {
+            http,
+            tls: TlsConnector::from(tls),
+        })
+    }
+}
+
+impl fmt::Debug for HttpsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("HttpsConnector")
+    }
+}
+
+impl Service for HttpsConnector {
+    type Request = Uri;
+    type Response = MaybeHttpsStream;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = MaybeHttpsStream, Error = io::Error>>;
+
+    fn call(&self, uri: Uri) -> Self::Future {
+        let host = match uri.host() {
+            Some(host) => host.to_string(),
+            None => {
+                return Box::new(future::err(
+                    io::Error::new(io::ErrorKind::InvalidInput, "URL has no host"),
+                ))
+            }
+        };
+
+        let is_https = uri.scheme() == Some("https");
+        let connecting = self.http.call(uri);
+
+        if !is_https {
+            return Box::new(connecting.map(MaybeHttpsStream::Http));
+        }
+
+        let tls = self.tls.clone();
+        Box::new(connecting.and_then(move |tcp| {
+            tls.connect(&host, tcp)
+                .map(MaybeHttpsStream::Https)
+                .map_err(io::Error::other)
+        }))
+    }
+}
+
+/// A connection made by `HttpsConnector`.
+pub enum MaybeHttpsStream {
+    Http(TcpStream),
+    Https(TlsStream<TcpStream>),
+}
+
+impl fmt::Debug for MaybeHttpsStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MaybeHttpsStream::Http(..) => f.pad("Http(..)"),
+            MaybeHttpsStream::Https(..) => f.pad("Https(..)"),
+        }
+    }
+}
+
+impl Read for MaybeHttpsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            MaybeHttpsStream::Http(ref mut s) => s.read(buf),
+            MaybeHttpsStream::Https(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for MaybeHttpsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            MaybeHttpsStream::Http(ref mut s) => s.write(buf),
+            MaybeHttpsStream::Https(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            MaybeHttpsStream::Http(ref mut s) => s.flush(),
+            MaybeHttpsStream::Https(ref mut s) => s.flush(),
+        }
+    }
+}
+
+impl AsyncRead for MaybeHttpsStream {}
+
+impl AsyncWrite for MaybeHttpsStream {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match *self {
+            MaybeHttpsStream::Http(ref mut s) => s.shutdown(),
+            MaybeHttpsStream::Https(ref mut s) => s.shutdown(),
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! Storage for registered apps and access tokens, so they can be reused
+//! between runs.
+
+use api::oauth::{App, CreateAppResponse, ScopeSet, Scopes, TokenResponse};
+use error::*;
+use rand::{self, Rng};
+use serde_json;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Everything in a credential store. Apps are keyed by instance URL, and
+/// tokens by instance URL and then account.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Credentials {
+    #[serde(default)]
+    pub apps: BTreeMap<String, StoredApp>,
+    #[serde(default)]
+    pub tokens: BTreeMap<String, BTreeMap<String, TokenResponse>>,
+}
+
+/// A registered app, along with the scopes it was registered with, since
+/// it can't be authorized for any others.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StoredApp {
+    #[serde(flatten)]
+    pub app: CreateAppResponse,
+    /// Space-separated, as sent when registering. Apps saved without them
+    /// don't cover any scopes, so they are registered again.
+    #[serde(default)]
+    pub scopes: String,
+}
+
+impl StoredApp {
+    pub fn new(app: CreateAppResponse, scopes: &Scopes) -> Self {
+        StoredApp {
+            app,
+            scopes: scopes.0.to_string(),
+        }
+    }
+
+    /// Whether the app was registered with every scope in `scopes`, or with
+    /// broader ones.
+    pub fn covers(&self, scopes: &Scopes) -> bool {
+        ScopeSet::parse(&self.scopes).implies_all(&ScopeSet::parse(&scopes.0))
+    }
+}
+
+/// A place to keep credentials. Implementations only need to load and save
+/// the whole set; the other methods are built on those.
+///
+/// Instance URLs are compared without trailing slashes. Accounts can be any
+/// string that identifies the user, such as `Account::acct`.
+pub trait CredentialStore {
+    fn load(&self) -> Result<Credentials>;
+
+    fn save(&self, credentials: &Credentials) -> Result<()>;
+
+    fn app(&self, instance_url: &str) -> Result<Option<StoredApp>> {
+        Ok(self.load()?.apps.remove(instance_key(instance_url)))
+    }
+
+    /// The app stored for `instance_url`, if it was registered with the
+    /// redirect URI of `app` and covers its scopes, so that it can be used
+    /// instead of registering `app` again.
+    fn reusable_app(&self, instance_url: &str, app: &App) -> Result<Option<StoredApp>> {
+        Ok(self.app(instance_url)?.filter(|stored| {
+            stored.app.redirect_uri == app.redirect_uris && stored.covers(&app.scopes)
+        }))
+    }
+
+    fn set_app(&self, instance_url: &str, app: &StoredApp) -> Result<()> {
+        let mut credentials = self.load()?;
+        credentials
+            .apps
+            .insert(instance_key(instance_url).to_string(), app.clone());
+        self.save(&credentials)
+    }
+
+    fn token(&self, instance_url: &str, account: &str) -> Result<Option<TokenResponse>> {
+        Ok(self.load()?
+            .tokens
+            .remove(instance_key(instance_url))
+            .and_then(|mut tokens| tokens.remove(account)))
+    }
+
+    fn set_token(&self, instance_url: &str, account: &str, token: &TokenResponse) -> Result<()> {
+        let mut credentials = self.load()?;
+        credentials
+            .tokens
+            .entry(instance_key(instance_url).to_string())
+            .or_insert_with(BTreeMap::new)
+            .insert(account.to_string(), token.clone());
+        self.save(&credentials)
+    }
+
+    fn remove_token(&self, instance_url: &str, account: &str) -> Result<()> {
+        let mut credentials = self.load()?;
+        let key = instance_key(instance_url);
+
+        let now_empty = match credentials.tokens.get_mut(key) {
+            Some(tokens) => {
+                tokens.remove(account);
+                tokens.is_empty()
+            }
+            None => return Ok(()),
+        };
+        if now_empty {
+            credentials.tokens.remove(key);
+        }
+
+        self.save(&credentials)
+    }
+}
+
+fn instance_key(instance_url: &str) -> &str {
+    instance_url.trim_end_matches('/')
+}
+
+/// Keeps credentials in memory only. Clones share the same storage.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore(Rc<RefCell<Credentials>>);
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+impl CredentialStore for MemoryStore {
+    fn load(&self) -> Result<Credentials> {
+        Ok(self.0.borrow().clone())
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<()> {
+        *self.0.borrow_mut() = credentials.clone();
+        Ok(())
+    }
+}
+
+/// Keeps credentials in a JSON file, which is created on the first save.
+///
+/// Saves write to a temporary file next to it and rename it into place, so
+/// the file is never left half-written, even after a crash. On Unix, the
+/// file is only readable and writable by its owner.
+///
+/// Only one process should use the file at a time. Each save is atomic, but
+/// methods such as `set_token` load, modify and save the whole file, so
+/// concurrent writers can lose each other's changes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        JsonFileStore { path: path.into() }
+    }
+
+    fn error(&self) -> ErrorKind {
+        ErrorKind::Credentials(self.path.display().to_string())
+    }
+}
+
+impl CredentialStore for JsonFileStore {
+    fn load(&self) -> Result<Credentials> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Credentials::default()),
+            Err(e) => return Err(e).chain_err(|| self.error()),
+        };
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .chain_err(|| self.error())?;
+
+        // The contents are secret, so they're left out of the error
+        serde_json::from_str(&contents).chain_err(|| self.error())
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<()> {
+        let json = serde_json::to_vec_pretty(credentials).chain_err(|| self.error())?;
+
+        // A unique name, so that concurrent saves don't clobber each other's
+        // temporary files
+        let suffix = rand::thread_rng()
+            .gen_ascii_chars()
+            .take(16)
+            .collect::<String>();
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(format!(".{}.tmp", suffix));
+        let temp_path = PathBuf::from(temp_path);
+
+        // Permissions only apply to new files
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        owner_only(&mut options);
+
+        let mut file = options.open(&temp_path).chain_err(|| self.error())?;
+
+        let written = file.write_all(&json)
+            .and_then(|_| file.sync_all())
+            .and_then(|_| fs::rename(&temp_path, &self.path));
+
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e).chain_err(|| self.error());
+        }
+
+        sync_dir(&self.path).chain_err(|| self.error())
+    }
+}
+
+// Flush the rename to disk, by syncing the directory that contains `path`
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn owner_only(options: &mut OpenOptions) {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+}
+
+#[cfg(not(unix))]
+fn owner_only(_options: &mut OpenOptions) {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn app() -> StoredApp {
+        let app = CreateAppResponse {
+            id: 1,
+            redirect_uri: "http://127.0.0.1:8080/callback".to_string(),
+            client_id: "id".to_string(),
+            client_secret: "secret".to_string(),
+        };
+        StoredApp::new(app, &Scopes::from_str("read write:media"))
+    }
+
+    fn token(access_token: &str) -> TokenResponse {
+        TokenResponse {
+            access_token: access_token.to_string(),
+            refresh_token: None,
+            token_type: "Bearer".to_string(),
+            expires_in: None,
+            scope: Some("read".to_string()),
+            created_at: None,
+        }
+    }
+
+    fn round_trip<S: CredentialStore>(store: &S) {
+        assert_eq!(store.app("https://example.com").unwrap(), None);
+
+        store.set_app("https://example.com/", &app()).unwrap();
+        store.set_token("https://example.com", "alice", &token("a")).unwrap();
+        store.set_token("https://example.com", "bob", &token("b")).unwrap();
+
+        assert_eq!(store.app("https://example.com").unwrap(), Some(app()));
+        assert_eq!(
+            store.token("https://example.com/", "alice").unwrap(),
+            Some(token("a"))
+        );
+
+        store.remove_token("https://example.com", "alice").unwrap();
+        assert_eq!(store.token("https://example.com", "alice").unwrap(), None);
+        assert_eq!(store.token("https://example.com", "bob").unwrap(), Some(token("b")));
+
+        store.remove_token("https://example.com", "bob").unwrap();
+        assert!(store.load().unwrap().tokens.is_empty());
+    }
+
+    #[test]
+    fn memory_store() {
+        let store = MemoryStore::new();
+        round_trip(&store);
+
+        let shared = store.clone();
+        store.set_app("https://example.com", &app()).unwrap();
+        assert_eq!(shared.app("https://example.com").unwrap(), Some(app()));
+    }
+
+    #[test]
+    fn stored_app_scopes() {
+        let app = app();
+        assert!(app.covers(&Scopes::from_str("read:statuses write:media")));
+        assert!(!app.covers(&Scopes::from_str("read write")));
+
+        // Apps saved before scopes were stored
+        let json = r#"{"id":1,"redirect_uri":"","client_id":"id","client_secret":"secret"}"#;
+        let old: StoredApp = serde_json::from_str(json).unwrap();
+        assert_eq!(old.app.client_id, "id");
+        assert!(!old.covers(&Scopes::from_str("read")));
+    }
+
+    #[test]
+    fn reusable_app() {
+        let store = MemoryStore::new();
+        let registration = |redirect_uris, scopes| {
+            App {
+                client_name: "olifants",
+                redirect_uris,
+                scopes: Scopes::from_str(scopes),
+                website: "",
+            }
+        };
+        let callback = "http://127.0.0.1:8080/callback";
+
+        assert_eq!(
+            store.reusable_app("https://example.com", &registration(callback, "read")).unwrap(),
+            None
+        );
+
+        store.set_app("https://example.com", &app()).unwrap();
+        let reuse = |redirect_uris, scopes| {
+            store
+                .reusable_app("https://example.com/", &registration(redirect_uris, scopes))
+                .unwrap()
+        };
+
+        assert_eq!(reuse(callback, "read:accounts write:media"), Some(app()));
+        assert_eq!(reuse("http://127.0.0.1:9090/callback", "read"), None);
+        assert_eq!(reuse(callback, "read follow"), None);
+    }
+
+    #[test]
+    fn json_file_store() {
+        let name = rand::thread_rng().gen_ascii_chars().take(16).collect::<String>();
+        let path = env::temp_dir().join(format!("olifants-{}.json", name));
+        let store = JsonFileStore::new(path.clone());
+
+        round_trip(&store);
+
+        let prefix = format!("olifants-{}.json.", name);
+        let leftover = fs::read_dir(env::temp_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&prefix));
+        assert!(!leftover);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::write(&path, "not json").unwrap();
+        assert!(store.load().is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,10 +1,17 @@
 use hyper;
+use serde_json;
+use std::collections::HashMap;
+use std::fmt;
 
 error_chain!{
     errors {
         Initialization {
             description("failed to initialize client")
         }
+        MissingHandle {
+            description("client has no reactor handle")
+            display("client has no reactor handle; call `Client::with_handle` first")
+        }
         Uri(uri: String) {
             description("invalid URI")
             display("could not parse URI: `{}`", uri)
@@ -12,39 +19,224 @@ error_chain!{
         Http {
             description("HTTP error")
         }
-        StatusCode(
-            status: hyper::StatusCode,
-            version: hyper::HttpVersion,
-            headers: hyper::Headers,
-            body: String
-        ) {
+        Api(error: Box<ApiError>) {
             description("Received non-2XX status code from server")
-            display(
-                "HTTP error\n{} {}\n{}\n{}",
-                version,
-                status,
-                headers,
-                body
-            )
+            display("{}", error)
         }
         Deserialize(value: String) {
             description("could not deserialize value")
             display("could not deserialize value: `{}`", value)
         }
+        Io {
+            description("I/O error")
+        }
+        File(path: String) {
+            description("could not read file")
+            display("could not read file `{}`", path)
+        }
+        Credentials(path: String) {
+            description("could not access credential store")
+            display("could not access credential store `{}`", path)
+        }
+        WebSocket(reason: String) {
+            description("WebSocket error")
+            display("WebSocket error: {}", reason)
+        }
+        Blurhash(hash: String) {
+            description("invalid BlurHash")
+            display("invalid BlurHash: `{}`", hash)
+        }
+        BlurhashSize(width: u32, height: u32) {
+            description("BlurHash image size is too large")
+            display("BlurHash image size {}x{} is too large", width, height)
+        }
         Utf8 {
             description("bytes contained invalid UTF-8")
         }
-        EventType(value: String) {
-            description("unknown event type")
-            display("unknown event type returned from API: `{}`", value)
+        Timer {
+            description("failed to set timer")
+        }
+        Reconnect(attempts: u32) {
+            description("failed to reconnect to streaming API")
+            display("failed to reconnect to streaming API after {} attempts", attempts)
+        }
+        Version(value: String) {
+            description("could not parse server version")
+            display("could not parse server version `{}`", value)
         }
-        StreamingState(expected: &'static str, actual: String) {
-            description("streaming API is in an unexpected state")
-            display("expected `{}` from streaming API, received `{}`", expected, actual)
+        Authorization(reason: String) {
+            description("authorization failed")
+            display("authorization failed: {}", reason)
+        }
+        OAuthState {
+            description("OAuth state did not match the authorization request")
+        }
+        Scope(value: String) {
+            description("unknown OAuth scope")
+            display("unknown OAuth scope `{}`", value)
         }
         StatusId(value: String) {
             description("received invalid status ID from API")
             display("could not parse status ID `{}` as an integer", value)
         }
+        MediaProcessing(id: String) {
+            description("media was not processed in time")
+            display("media attachment `{}` was not processed in time", id)
+        }
+    }
+}
+
+impl Error {
+    /// The class of error returned by the server, or `None` if the request
+    /// failed for some other reason.
+    pub fn class(&self) -> Option<ErrorClass> {
+        match *self.kind() {
+            ErrorKind::Api(ref error) => Some(error.class()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ErrorClass {
+    /// `401`: the access token is missing, invalid or revoked.
+    Unauthorized,
+    /// `403`: the token lacks a required scope, or the action isn't allowed.
+    Forbidden,
+    /// `404` or `410`: the resource doesn't exist or isn't visible.
+    NotFound,
+    /// `422`: the request failed validation.
+    Unprocessable,
+    /// `429`: too many requests.
+    RateLimited,
+    /// `5XX`.
+    Server,
+    Other,
+}
+
+/// A non-2XX response from the server.
+#[derive(Clone, Debug)]
+pub struct ApiError {
+    pub status: hyper::StatusCode,
+    pub version: hyper::HttpVersion,
+    pub headers: hyper::Headers,
+    pub body: String,
+    /// Mastodon's error message, or the error code on OAuth endpoints.
+    pub error: Option<String>,
+    /// Only sent by OAuth endpoints.
+    pub error_description: Option<String>,
+    /// Validation errors keyed by attribute, sent with some `422` responses.
+    pub details: HashMap<String, Vec<ValidationError>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ValidationError {
+    /// A code such as `ERR_BLANK` or `ERR_TOO_LONG`.
+    pub error: String,
+    pub description: String,
+}
+
+#[derive(Default, Deserialize)]
+struct ErrorBody {
+    error: Option<String>,
+    error_description: Option<String>,
+    #[serde(default)]
+    details: HashMap<String, Vec<ValidationError>>,
+}
+
+impl ApiError {
+    /// Parse the error from the response body, if it is JSON in the form
+    /// Mastodon uses.
+    pub fn new(
+        status: hyper::StatusCode,
+        version: hyper::HttpVersion,
+        headers: hyper::Headers,
+        body: String,
+    ) -> Self {
+        let parsed = serde_json::from_str::<ErrorBody>(&body).unwrap_or_default();
+
+        ApiError {
+            status,
+            version,
+            headers,
+            body,
+            error: parsed.error,
+            error_description: parsed.error_description,
+            details: parsed.details,
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self.status.as_u16() {
+            401 => ErrorClass::Unauthorized,
+            403 => ErrorClass::Forbidden,
+            404 | 410 => ErrorClass::NotFound,
+            422 => ErrorClass::Unprocessable,
+            429 => ErrorClass::RateLimited,
+            500..=599 => ErrorClass::Server,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HTTP error\n{} {}", self.version, self.status)?;
+
+        match (self.error.as_ref(), self.error_description.as_ref()) {
+            (Some(error), Some(description)) => write!(f, "\n{}: {}", error, description),
+            (Some(error), None) => write!(f, "\n{}", error),
+            _ => write!(f, "\n{}\n{}", self.headers, self.body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn api_error(status: hyper::StatusCode, body: &str) -> ApiError {
+        ApiError::new(
+            status,
+            hyper::HttpVersion::Http11,
+            hyper::Headers::new(),
+            body.to_string(),
+        )
+    }
+
+    #[test]
+    fn parse_error_body() {
+        let error = api_error(
+            hyper::StatusCode::Unauthorized,
+            r#"{"error":"invalid_grant","error_description":"The provided authorization grant is invalid."}"#,
+        );
+        assert_eq!(error.class(), ErrorClass::Unauthorized);
+        assert_eq!(error.error, Some("invalid_grant".to_string()));
+        assert!(error.error_description.is_some());
+
+        let error = api_error(
+            hyper::StatusCode::UnprocessableEntity,
+            r#"{
+                "error": "Validation failed: Text can't be blank",
+                "details": { "text": [{ "error": "ERR_BLANK", "description": "can't be blank" }] }
+            }"#,
+        );
+        assert_eq!(error.class(), ErrorClass::Unprocessable);
+        assert_eq!(error.details["text"][0].error, "ERR_BLANK");
+
+        let error = api_error(hyper::StatusCode::BadGateway, "<html>Bad Gateway</html>");
+        assert_eq!(error.class(), ErrorClass::Server);
+        assert_eq!(error.error, None);
+        assert_eq!(error.body, "<html>Bad Gateway</html>");
+    }
+
+    #[test]
+    fn error_class() {
+        let error: Error = ErrorKind::Api(Box::new(api_error(hyper::StatusCode::TooManyRequests, ""))).into();
+        assert_eq!(error.class(), Some(ErrorClass::RateLimited));
+        assert_eq!(api_error(hyper::StatusCode::Gone, "").class(), ErrorClass::NotFound);
+
+        let error: Error = ErrorKind::Timer.into();
+        assert_eq!(error.class(), None);
     }
 }
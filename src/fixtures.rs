@@ -0,0 +1,47 @@
+//! Data shared by the unit tests.
+
+use api::v1::Status;
+use serde_json;
+
+/// A minimal status with the given ID, as the API would return it.
+pub fn status(id: &str) -> Status {
+    let json = format!(
+        r#"{{
+            "id": "{}",
+            "uri": "",
+            "url": "",
+            "account": {{
+                "id": "1",
+                "username": "",
+                "acct": "",
+                "display_name": "",
+                "locked": false,
+                "created_at": "2017-01-01T00:00:00Z",
+                "followers_count": 0,
+                "following_count": 0,
+                "statuses_count": 0,
+                "note": "",
+                "url": "",
+                "avatar": "",
+                "avatar_static": "",
+                "header": "",
+                "header_static": ""
+            }},
+            "in_reply_to_id": null,
+            "in_reply_to_account_id": null,
+            "reblog": null,
+            "content": "",
+            "created_at": "2017-01-01T00:00:00Z",
+            "reblogs_count": 0,
+            "favourites_count": 0,
+            "spoiler_text": "",
+            "visibility": "public",
+            "media_attachments": [],
+            "mentions": [],
+            "tags": []
+        }}"#,
+        id
+    );
+
+    serde_json::from_str(&json).unwrap()
+}
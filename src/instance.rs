@@ -0,0 +1,311 @@
+//! Instance metadata, combining the responses of `/api/v1/instance` and
+//! `/api/v2/instance` into one type.
+
+use api;
+use api::v1::{MediaConfiguration, PollConfiguration, StatusConfiguration};
+use error::*;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+    pub domain: String,
+    pub title: String,
+    pub description: String,
+    /// The version as reported by the server, which may include suffixes
+    /// such as `+glitch` or `(compatible; Pleroma 2.5.0)`.
+    pub version_string: String,
+    /// The parsed version, or `None` if it couldn't be parsed.
+    pub version: Option<Version>,
+    /// Base URL of the streaming API, if the instance advertises one.
+    pub streaming_api: Option<String>,
+    /// Reported by `/api/v1/instance` only, so `None` unless the instance
+    /// was fetched from it or with `Client::instance_with_stats`.
+    pub stats: Option<api::v1::InstanceStats>,
+    /// Users active in the past 30 days. Only available from
+    /// `/api/v2/instance`.
+    pub active_users: Option<u64>,
+    pub registrations: bool,
+    pub approval_required: bool,
+    pub statuses: StatusConfiguration,
+    pub media_attachments: MediaConfiguration,
+    pub polls: PollConfiguration,
+}
+
+impl Instance {
+    /// Whether the server is Mastodon `version` or newer. Servers with an
+    /// unparseable version are assumed not to be.
+    pub fn at_least(&self, version: Version) -> bool {
+        match self.version {
+            Some(ref v) => *v >= version,
+            None => false,
+        }
+    }
+}
+
+impl From<api::v1::Instance> for Instance {
+    fn from(instance: api::v1::Instance) -> Self {
+        let configuration = instance.configuration.unwrap_or_default();
+
+        Instance {
+            domain: instance.uri,
+            title: instance.title,
+            description: instance.description,
+            version: instance.version.parse().ok(),
+            version_string: instance.version,
+            streaming_api: instance.urls.streaming_api,
+            stats: Some(instance.stats),
+            active_users: None,
+            registrations: instance.registrations,
+            approval_required: instance.approval_required,
+            statuses: configuration.statuses,
+            media_attachments: configuration.media_attachments,
+            polls: configuration.polls,
+        }
+    }
+}
+
+impl From<api::v2::Instance> for Instance {
+    fn from(instance: api::v2::Instance) -> Self {
+        let configuration = instance.configuration;
+
+        Instance {
+            domain: instance.domain,
+            title: instance.title,
+            description: instance.description,
+            version: instance.version.parse().ok(),
+            version_string: instance.version,
+            streaming_api: configuration.urls.streaming,
+            stats: None,
+            active_users: Some(instance.usage.users.active_month),
+            registrations: instance.registrations.enabled,
+            approval_required: instance.registrations.approval_required,
+            statuses: configuration.statuses,
+            media_attachments: configuration.media_attachments,
+            polls: configuration.polls,
+        }
+    }
+}
+
+/// Only the streaming API URL from `/api/v2/instance` or
+/// `/api/v1/instance`, for looking up the streaming host without depending
+/// on the rest of the metadata.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct StreamingApi {
+    configuration: Option<StreamingConfiguration>,
+    urls: Option<api::v1::InstanceUrls>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+struct StreamingConfiguration {
+    urls: Option<api::v2::ConfigurationUrls>,
+}
+
+impl StreamingApi {
+    /// The advertised URL, taken from `configuration.urls.streaming` in v2
+    /// responses or `urls.streaming_api` in v1 responses.
+    pub fn url(self) -> Option<String> {
+        let StreamingApi { configuration, urls } = self;
+        let v2 = configuration.and_then(|c| c.urls).and_then(
+            |urls| urls.streaming,
+        );
+        v2.or_else(|| urls.and_then(|urls| urls.streaming_api))
+    }
+}
+
+/// A Mastodon version number. Pre-releases such as `4.0.0rc1` or
+/// `4.3.0-beta.1` sort before the release they precede, and build metadata
+/// such as `+glitch` is ignored.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub pre: Option<String>,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+            pre: None,
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let error = || ErrorKind::Version(value.to_string());
+
+        // Other servers append their own version, e.g. "2.7.2 (compatible; Pleroma 2.5.0)"
+        let version = value.split_whitespace().next().ok_or_else(error)?;
+        let version = version.split('+').next().unwrap_or(version);
+
+        let mut parts = version.splitn(3, '.');
+        let major = parts.next().and_then(|s| s.parse().ok()).ok_or_else(error)?;
+        let minor = parts.next().and_then(|s| s.parse().ok()).ok_or_else(error)?;
+        let rest = parts.next().unwrap_or("0");
+
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let patch = rest[..digits].parse().map_err(|_| error())?;
+        let pre = rest[digits..].trim_start_matches('-');
+
+        Ok(Version {
+            major,
+            minor,
+            patch,
+            pre: if pre.is_empty() {
+                None
+            } else {
+                Some(pre.to_string())
+            },
+        })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (self.pre.as_ref(), other.pre.as_ref()) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => pre_release_parts(a).cmp(&pre_release_parts(b)),
+            })
+    }
+}
+
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum PreReleasePart<'a> {
+    Number(u64),
+    Text(&'a str),
+}
+
+// Split a pre-release such as `rc10` or `beta.2` into runs of digits and
+// other characters, so that numbers compare by value and `rc2` < `rc10`
+fn pre_release_parts(pre: &str) -> Vec<PreReleasePart<'_>> {
+    let mut parts = Vec::new();
+
+    for segment in pre.split(&['.', '-'][..]) {
+        let mut rest = segment;
+        while !rest.is_empty() {
+            let numeric = rest.starts_with(|c: char| c.is_ascii_digit());
+            let end = rest.find(|c: char| c.is_ascii_digit() != numeric).unwrap_or(rest.len());
+            let (run, tail) = rest.split_at(end);
+
+            parts.push(match run.parse() {
+                Ok(number) if numeric => PreReleasePart::Number(number),
+                _ => PreReleasePart::Text(run),
+            });
+            rest = tail;
+        }
+    }
+
+    parts
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(ref pre) = self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn parse_version() {
+        assert_eq!("4.2.1".parse::<Version>().unwrap(), Version::new(4, 2, 1));
+        assert_eq!("3.5.3+glitch".parse::<Version>().unwrap(), Version::new(3, 5, 3));
+        assert_eq!(
+            "2.7.2 (compatible; Pleroma 2.5.0)".parse::<Version>().unwrap(),
+            Version::new(2, 7, 2)
+        );
+        assert_eq!("4.0".parse::<Version>().unwrap(), Version::new(4, 0, 0));
+
+        let rc = "4.0.0rc1".parse::<Version>().unwrap();
+        assert_eq!(rc.pre, Some("rc1".to_string()));
+        let beta = "4.3.0-beta.1".parse::<Version>().unwrap();
+        assert_eq!(beta.to_string(), "4.3.0-beta.1");
+
+        assert!("".parse::<Version>().is_err());
+        assert!("four".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn compare_versions() {
+        let v = |s: &str| s.parse::<Version>().unwrap();
+
+        assert!(v("4.0.0") > v("3.5.10"));
+        assert!(v("3.5.10") > v("3.5.9"));
+        assert!(v("4.0.0") > v("4.0.0rc2"));
+        assert!(v("4.0.0rc2") > v("4.0.0rc1"));
+        assert!(v("4.0.0rc10") > v("4.0.0rc2"));
+        assert!(v("4.3.0-beta.10") > v("4.3.0-beta.2"));
+        assert!(v("4.3.0-rc.1") > v("4.3.0-beta.2"));
+        assert_eq!(v("3.5.3+glitch"), v("3.5.3"));
+    }
+
+    #[test]
+    fn from_v1_instance() {
+        let instance: api::v1::Instance = serde_json::from_str(
+            r#"{
+                "uri": "example.com",
+                "title": "Example",
+                "description": "",
+                "email": "admin@example.com",
+                "version": "3.5.3",
+                "urls": { "streaming_api": "wss://streaming.example.com" },
+                "stats": { "user_count": 10, "status_count": 100, "domain_count": 5 },
+                "registrations": true,
+                "configuration": { "statuses": { "max_characters": 500 } }
+            }"#,
+        ).unwrap();
+        let instance = Instance::from(instance);
+
+        assert_eq!(instance.streaming_api, Some("wss://streaming.example.com".to_string()));
+        assert_eq!(instance.stats.as_ref().unwrap().user_count, 10);
+        assert_eq!(instance.statuses.max_characters, 500);
+        assert!(instance.registrations);
+        assert!(instance.at_least(Version::new(3, 5, 0)));
+        assert!(!instance.at_least(Version::new(4, 0, 0)));
+    }
+
+    #[test]
+    fn streaming_api() {
+        let v2: StreamingApi = serde_json::from_str(
+            r#"{
+                "domain": "example.com",
+                "configuration": { "urls": { "streaming": "wss://streaming.example.com" } }
+            }"#,
+        ).unwrap();
+        assert_eq!(v2.url(), Some("wss://streaming.example.com".to_string()));
+
+        let v1: StreamingApi = serde_json::from_str(
+            r#"{
+                "uri": "example.com",
+                "urls": { "streaming_api": "wss://example.com" },
+                "configuration": { "statuses": { "max_characters": 500 } }
+            }"#,
+        ).unwrap();
+        assert_eq!(v1.url(), Some("wss://example.com".to_string()));
+    }
+}
@@ -7,46 +7,94 @@ extern crate error_chain;
 #[macro_use]
 extern crate futures;
 
+extern crate base64;
 extern crate chrono;
 extern crate hyper;
-extern crate hyper_tls;
+extern crate native_tls;
+extern crate rand;
 extern crate serde;
 extern crate serde_json;
+extern crate sha1;
+extern crate sha2;
 extern crate tokio_core;
+extern crate tokio_io;
+extern crate tokio_tls;
 extern crate url;
 
 pub mod error;
 pub mod api;
+pub mod blurhash;
+pub mod connector;
+pub mod credentials;
+pub mod instance;
+pub mod loopback;
+pub mod multipart;
+pub mod page;
+pub mod ratelimit;
+pub mod reconnect;
+pub mod sse;
+pub mod thread;
 pub mod timeline;
+pub mod token;
+pub mod websocket;
 
+#[cfg(test)]
+mod fixtures;
+
+use connector::HttpsConnector;
 use error::*;
 use futures::{Future, IntoFuture, Stream, future};
+use hyper::client::Service;
 use hyper::header::UserAgent;
-use hyper_tls::HttpsConnector;
 use std::borrow::Cow;
-use tokio_core::reactor::Handle;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::{Handle, Timeout};
 
-pub struct Client<H = HttpsConnector<hyper::client::HttpConnector>> {
+#[derive(Clone)]
+pub struct Client<H = HttpsConnector> {
     http: hyper::client::Client<H>,
+    handle: Option<Handle>,
+    connector: Rc<RefCell<Option<HttpsConnector>>>,
     user_agent: UserAgent,
+    streaming_urls: Rc<RefCell<HashMap<String, StreamingUrl>>>,
+    rate_limits: Rc<RefCell<HashMap<RateLimitKey, ratelimit::RateLimit>>>,
+    throttle: Option<ratelimit::Throttle>,
 }
 
+/// How long to use the instance URL for streaming after the streaming host
+/// couldn't be looked up, before trying again.
+const STREAMING_URL_RETRY: Duration = Duration::from_secs(5 * 60);
+
+/// A cached streaming host, and when to look it up again if it is only a
+/// fallback.
+type StreamingUrl = (String, Option<Instant>);
+
+/// Rate limits apply per token, or per IP address for requests without one,
+/// so they are tracked by instance and token.
+type RateLimitKey = (String, Option<String>);
+
 impl Client {
     pub fn new<U>(handle: &Handle, user_agent: U) -> Result<Self>
     where
         U: Into<Cow<'static, str>>,
     {
-        let connector = HttpsConnector::new(4, &handle).chain_err(
-            || ErrorKind::Initialization,
-        )?;
+        let connector = HttpsConnector::new(4, handle)?;
 
-        let http = hyper::Client::configure().connector(connector).build(
-            &handle,
-        );
+        let http = hyper::Client::configure()
+            .connector(connector.clone())
+            .build(handle);
 
         Ok(Client {
             http,
+            handle: Some(handle.clone()),
+            connector: Rc::new(RefCell::new(Some(connector))),
             user_agent: UserAgent::new(user_agent),
+            streaming_urls: Rc::default(),
+            rate_limits: Rc::default(),
+            throttle: None,
         })
     }
 
@@ -56,8 +104,62 @@ impl Client {
     {
         Client {
             http: hyper,
+            handle: None,
+            connector: Rc::default(),
             user_agent: UserAgent::new(user_agent),
+            streaming_urls: Rc::default(),
+            rate_limits: Rc::default(),
+            throttle: None,
+        }
+    }
+}
+
+impl<H> Client<H>
+where
+    H: hyper::client::Connect + Clone,
+{
+    /// Use `handle` for timers and extra connections. Reconnecting and
+    /// WebSocket timelines, throttled retries, media processing polls and
+    /// loopback logins need one, and clients created with `new` already
+    /// have it.
+    pub fn with_handle(mut self, handle: &Handle) -> Self {
+        self.handle = Some(handle.clone());
+        self
+    }
+
+    fn handle(&self) -> Result<Handle> {
+        self.handle.clone().ok_or_else(
+            || ErrorKind::MissingHandle.into(),
+        )
+    }
+
+    /// Wait for `delay`, which needs a handle unless the delay is zero.
+    fn sleep(&self, delay: Duration) -> impl Future<Item = (), Error = Error> {
+        if delay == Duration::default() {
+            return future::Either::A(future::ok(()));
+        }
+
+        let sleep = self.handle()
+            .and_then(|handle| {
+                Timeout::new(delay, &handle).chain_err(|| ErrorKind::Timer)
+            })
+            .into_future()
+            .and_then(|timeout| timeout.then(|r| r.chain_err(|| ErrorKind::Timer)));
+
+        future::Either::B(sleep)
+    }
+
+    /// The connector for connections made outside of the hyper client, such
+    /// as WebSockets. Clients created with `from_hyper_client` get one the
+    /// first time it is needed, and keep it.
+    fn connector(&self) -> Result<HttpsConnector> {
+        if let Some(ref connector) = *self.connector.borrow() {
+            return Ok(connector.clone());
         }
+
+        let connector = HttpsConnector::new(1, &self.handle()?)?;
+        *self.connector.borrow_mut() = Some(connector.clone());
+        Ok(connector)
     }
 
     fn request<F>(
@@ -74,9 +176,7 @@ impl Client {
             req.headers_mut().set(self.user_agent.clone());
             req = modify_request(req);
 
-            self.http.request(req).then(
-                |r| r.chain_err(|| ErrorKind::Http),
-            )
+            self.send(req)
         }).into_future()
             .flatten();
 
@@ -95,41 +195,203 @@ impl Client {
                 .concat2()
                 .then(|r| r.chain_err(|| ErrorKind::Http))
                 .and_then(move |bytes| {
-                    bail!(ErrorKind::StatusCode(
+                    bail!(ErrorKind::Api(Box::new(ApiError::new(
                         status,
                         version,
                         headers,
                         String::from_utf8_lossy(&bytes).into(),
-                    ));
+                    ))));
                 });
 
             future::Either::B(as_error)
         })
     }
 
+    fn send(&self, req: hyper::Request) -> impl Future<Item = hyper::Response, Error = Error> {
+        let key = rate_limit_key(req.uri(), req.headers().get());
+        let rate_limits = self.rate_limits.clone();
+
+        let record = move |res: &hyper::Response| {
+            if let Some(rate_limit) = ratelimit::RateLimit::from_headers(res.headers()) {
+                rate_limits.borrow_mut().insert(key.clone(), rate_limit);
+            }
+        };
+
+        let throttle = match self.throttle {
+            Some(ref throttle) => throttle.clone(),
+            None => {
+                let response = self.http
+                    .request(req)
+                    .then(|r| r.chain_err(|| ErrorKind::Http))
+                    .map(move |res| {
+                        record(&res);
+                        res
+                    });
+                return future::Either::A(response);
+            }
+        };
+
+        // The request may need to be sent more than once, so buffer the body
+        let client = self.clone();
+        let (method, uri, version, headers, body) = req.deconstruct();
+
+        let response = body.concat2()
+            .then(|r| r.chain_err(|| ErrorKind::Http))
+            .and_then(move |body| {
+                let key = rate_limit_key(&uri, headers.get());
+                let initial_delay = client
+                    .rate_limits
+                    .borrow()
+                    .get(&key)
+                    .map(|rate_limit| throttle.delay(rate_limit, chrono::Utc::now()))
+                    .unwrap_or_default();
+
+                future::loop_fn((0, initial_delay), move |(attempt, delay)| {
+                    let mut req = hyper::Request::new(method.clone(), uri.clone());
+                    req.set_version(version);
+                    *req.headers_mut() = headers.clone();
+                    req.set_body(body.to_vec());
+
+                    let http = client.http.clone();
+                    let record = record.clone();
+                    let throttle = throttle.clone();
+
+                    client
+                        .sleep(delay)
+                        .and_then(move |_| {
+                            http.request(req).then(|r| r.chain_err(|| ErrorKind::Http))
+                        })
+                        .map(move |res| {
+                            record(&res);
+
+                            if res.status() == hyper::StatusCode::TooManyRequests &&
+                                attempt < throttle.max_retries
+                            {
+                                let rate_limit = ratelimit::RateLimit::from_headers(res.headers());
+                                let delay =
+                                    throttle.retry_delay(rate_limit.as_ref(), chrono::Utc::now());
+                                future::Loop::Continue((attempt + 1, delay))
+                            } else {
+                                future::Loop::Break(res)
+                            }
+                        })
+                })
+            });
+
+        future::Either::B(response)
+    }
+
     fn request_json<T, F>(
         &self,
         uri: Result<hyper::Uri>,
         method: hyper::Method,
         modify_request: F,
     ) -> impl Future<Item = T, Error = Error>
+    where
+        F: FnOnce(hyper::Request) -> hyper::Request,
+        T: serde::de::DeserializeOwned,
+    {
+        self.request(uri, method, modify_request).and_then(json_body)
+    }
+
+    fn request_page<T, F>(
+        &self,
+        uri: Result<hyper::Uri>,
+        method: hyper::Method,
+        modify_request: F,
+    ) -> impl Future<Item = page::Page<T>, Error = Error>
     where
         F: FnOnce(hyper::Request) -> hyper::Request,
         T: serde::de::DeserializeOwned,
     {
         self.request(uri, method, modify_request).and_then(|res| {
-            res.body()
-                .concat2()
-                .then(|r| r.chain_err(|| ErrorKind::Http))
-                .and_then(|bytes| {
-                    serde_json::from_slice(&bytes).chain_err(|| {
-                        let invalid_json = String::from_utf8_lossy(&bytes);
-                        ErrorKind::Deserialize(invalid_json.into())
-                    })
-                })
+            let link = res.headers().get::<hyper::header::Link>().cloned();
+            json_body(res).map(move |items| page::Page::new(items, link.as_ref()))
+        })
+    }
+
+    /// Fetch information about the instance from `/api/v2/instance`,
+    /// falling back to `/api/v1/instance` on servers older than Mastodon 4.0.
+    /// The v2 endpoint doesn't report statistics, so `stats` is only filled
+    /// in by the fallback; use `instance_with_stats` to always get them.
+    pub fn instance(
+        &self,
+        instance_url: &str,
+    ) -> impl Future<Item = instance::Instance, Error = Error> {
+        let client = self.clone();
+        let fallback_url = instance_url.to_string();
+
+        self.instance_v2(instance_url)
+            .map(instance::Instance::from)
+            .or_else(move |err| match err.class() {
+                Some(ErrorClass::NotFound) => {
+                    future::Either::A(
+                        client.instance_v1(&fallback_url).map(instance::Instance::from),
+                    )
+                }
+                _ => future::Either::B(future::err(err)),
+            })
+    }
+
+    /// Like `instance`, but when the v2 endpoint is available, statistics
+    /// are fetched with a second request to `/api/v1/instance`. They are
+    /// left empty if that request fails.
+    pub fn instance_with_stats(
+        &self,
+        instance_url: &str,
+    ) -> impl Future<Item = instance::Instance, Error = Error> {
+        let client = self.clone();
+        let stats_url = instance_url.to_string();
+
+        self.instance(instance_url).and_then(move |mut instance| {
+            if instance.stats.is_some() {
+                return future::Either::A(future::ok(instance));
+            }
+
+            future::Either::B(client.instance_v1(&stats_url).then(move |v1| {
+                instance.stats = v1.ok().map(|v1| v1.stats);
+                Ok(instance)
+            }))
         })
     }
 
+    pub fn instance_v1(
+        &self,
+        instance_url: &str,
+    ) -> impl Future<Item = api::v1::Instance, Error = Error> {
+        let request_url = api_uri(instance_url, "/api/v1/instance");
+        self.request_json(request_url, hyper::Method::Get, |req| req)
+    }
+
+    pub fn instance_v2(
+        &self,
+        instance_url: &str,
+    ) -> impl Future<Item = api::v2::Instance, Error = Error> {
+        let request_url = api_uri(instance_url, "/api/v2/instance");
+        self.request_json(request_url, hyper::Method::Get, |req| req)
+    }
+
+    /// Hold requests back when the rate limit is nearly used up, and retry
+    /// requests that were rate limited, according to `throttle`. Waiting
+    /// needs a reactor handle, so requests that have to wait fail with
+    /// `ErrorKind::MissingHandle` on clients without one (see `with_handle`).
+    pub fn throttle(mut self, throttle: ratelimit::Throttle) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// The rate limit from the latest response from `instance_url` for
+    /// `access_token`, or for unauthenticated requests if it is `None`.
+    pub fn rate_limit(
+        &self,
+        instance_url: &str,
+        access_token: Option<&str>,
+    ) -> Option<ratelimit::RateLimit> {
+        let uri = instance_url.parse::<hyper::Uri>().ok()?;
+        let key = rate_limit_key(&uri, access_token.map(bearer).as_ref());
+        self.rate_limits.borrow().get(&key).cloned()
+    }
+
     pub fn create_app(
         &self,
         instance_url: &str,
@@ -149,6 +411,7 @@ impl Client {
         })
     }
 
+    /// Exchange an authorization code for an access token.
     pub fn get_token(
         &self,
         instance_url: &str,
@@ -157,53 +420,1328 @@ impl Client {
         client_secret: &str,
         code: &str,
     ) -> impl Future<Item = api::oauth::TokenResponse, Error = Error> {
-        let base_url = format!("{}/oauth/token", instance_url);
-
-        let request_url = url::Url::parse(&base_url)
-            .chain_err(|| ErrorKind::Uri(base_url.to_string()))
-            .and_then(|mut url| {
-                url.query_pairs_mut()
-                    .append_pair("grant_type", "authorization_code")
-                    .append_pair("client_id", client_id)
-                    .append_pair("client_secret", client_secret)
-                    .append_pair("code", code)
-                    .append_pair("redirect_uri", redirect_uri);
-
-                url.as_str().parse::<hyper::Uri>().chain_err(|| {
-                    ErrorKind::Uri(url.into_string())
-                })
-            });
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "authorization_code")
+            .append_pair("client_id", client_id)
+            .append_pair("client_secret", client_secret)
+            .append_pair("code", code)
+            .append_pair("redirect_uri", redirect_uri)
+            .finish();
 
-        self.request_json(request_url, hyper::Method::Post, |req| req)
+        self.oauth_request(instance_url, "/oauth/token", body)
     }
 
-    pub fn timeline<S>(
+    /// Exchange the authorization code that the user was redirected back
+    /// with, after checking that the `state` they came back with matches
+    /// `request`. The PKCE code verifier is sent along with the code.
+    pub fn get_token_pkce(
         &self,
         instance_url: &str,
-        access_token: S,
-        endpoint: timeline::Endpoint,
-    ) -> impl Stream<Item = timeline::Event, Error = Error>
+        request: &api::oauth::AuthorizationRequest,
+        client_secret: &str,
+        code: &str,
+        state: &str,
+    ) -> impl Future<Item = api::oauth::TokenResponse, Error = Error> {
+        if state != request.state {
+            return future::Either::A(future::err(ErrorKind::OAuthState.into()));
+        }
+
+        let mut body = url::form_urlencoded::Serializer::new(String::new());
+        body.append_pair("grant_type", "authorization_code")
+            .append_pair("client_id", &request.client_id)
+            .append_pair("client_secret", client_secret)
+            .append_pair("code", code)
+            .append_pair("redirect_uri", &request.redirect_uri);
+        if let Some(ref verifier) = request.code_verifier {
+            body.append_pair("code_verifier", verifier);
+        }
+
+        future::Either::B(self.oauth_request(instance_url, "/oauth/token", body.finish()))
+    }
+
+    /// Reuse the app that was registered on `instance_url` earlier, if
+    /// `store` has one with the same redirect URI that was registered with
+    /// all of `app`'s scopes, or else register `app` and save it to `store`.
+    pub fn get_or_create_app<S>(
+        &self,
+        store: &S,
+        instance_url: &str,
+        app: &api::oauth::App,
+    ) -> impl Future<Item = api::oauth::CreateAppResponse, Error = Error>
     where
-        S: Into<String>,
+        S: credentials::CredentialStore + Clone,
     {
-        let request_url = format!("{}{}", instance_url, endpoint.as_path());
+        match store.reusable_app(instance_url, app) {
+            Ok(Some(existing)) => return future::Either::A(future::ok(existing.app)),
+            Ok(None) => {}
+            Err(e) => return future::Either::A(future::err(e)),
+        }
 
-        let parsed_url = request_url.parse().chain_err(|| {
-            ErrorKind::Uri(request_url.to_string())
+        let store = store.clone();
+        let instance_url = instance_url.to_string();
+        let scopes = app.scopes.0.to_string();
+
+        let created = self.create_app(&instance_url, app).and_then(move |app| {
+            let stored = credentials::StoredApp::new(app, &api::oauth::Scopes::from_str(&scopes));
+            store.set_app(&instance_url, &stored).map(|_| stored.app)
         });
 
-        let chunks = self.request(parsed_url, hyper::Method::Get, |mut req| {
-            req.headers_mut().set(hyper::header::Authorization(
-                hyper::header::Bearer { token: access_token.into() },
-            ));
-            req
-        }).map(|res| {
-                res.body().then(
-                    |result| result.chain_err(|| ErrorKind::Http),
+        future::Either::B(created)
+    }
+
+    /// Log in interactively, without the user having to copy and paste a
+    /// code. This gets an app whose redirect URI points at a listener on
+    /// `127.0.0.1` from `get_or_create_app`, calls `open_url` with the
+    /// authorization URL for the user to visit, then waits for their browser
+    /// to be redirected back and exchanges the code for a token.
+    ///
+    /// If `store` has an app from an earlier login that covers `scopes`, the
+    /// listener uses the port from its redirect URI so that it can be reused.
+    pub fn loopback_login<S, F>(
+        &self,
+        store: &S,
+        instance_url: &str,
+        client_name: &str,
+        scopes: &api::oauth::Scopes,
+        website: &str,
+        open_url: F,
+    ) -> impl Future<Item = loopback::Login, Error = Error>
+    where
+        S: credentials::CredentialStore + Clone,
+        F: FnOnce(&str),
+    {
+        let client = self.clone();
+        let instance_url = instance_url.to_string();
+
+        let port = match store.app(&instance_url) {
+            Ok(Some(ref existing)) if existing.covers(scopes) => {
+                loopback::redirect_port(&existing.app.redirect_uri)
+            }
+            _ => None,
+        };
+
+        let listener = self.handle().and_then(|handle| {
+            // The port may have been taken since, which means registering again
+            port.and_then(|port| loopback::Listener::bind_port(&handle, port).ok())
+                .map_or_else(|| loopback::Listener::bind(&handle), Ok)
+        });
+
+        let scopes = scopes.0.to_string();
+
+        let app = listener.map(|listener| {
+            let redirect_uri = listener.redirect_uri().to_string();
+            let app = api::oauth::App {
+                client_name,
+                redirect_uris: &redirect_uri,
+                scopes: api::oauth::Scopes::from_str(&scopes),
+                website,
+            };
+
+            (listener, self.get_or_create_app(store, &instance_url, &app))
+        });
+
+        app.into_future().and_then(move |(listener, app)| {
+            app.and_then(move |app| {
+                client
+                    .loopback_token(&instance_url, &app, listener, &scopes, open_url)
+                    .map(move |token| loopback::Login { app, token })
+            })
+        })
+    }
+
+    /// Like `loopback_login`, but for an app that `loopback_login`
+    /// registered earlier, such as one kept in a `CredentialStore`. The
+    /// listener uses the port from the app's redirect URI.
+    pub fn loopback_authorize<F>(
+        &self,
+        instance_url: &str,
+        app: &api::oauth::CreateAppResponse,
+        scopes: &api::oauth::Scopes,
+        open_url: F,
+    ) -> impl Future<Item = api::oauth::TokenResponse, Error = Error>
+    where
+        F: FnOnce(&str),
+    {
+        let listener = match loopback::redirect_port(&app.redirect_uri) {
+            Some(port) => {
+                self.handle().and_then(
+                    |handle| loopback::Listener::bind_port(&handle, port),
+                )
+            }
+            None => Err(
+                ErrorKind::Authorization(format!(
+                    "`{}` is not a loopback redirect URI",
+                    app.redirect_uri
+                )).into(),
+            ),
+        };
+
+        match listener {
+            Ok(listener) => future::Either::A(
+                self.loopback_token(instance_url, app, listener, &scopes.0, open_url),
+            ),
+            Err(e) => future::Either::B(future::err(e)),
+        }
+    }
+
+    fn loopback_token<F>(
+        &self,
+        instance_url: &str,
+        app: &api::oauth::CreateAppResponse,
+        listener: loopback::Listener,
+        scopes: &str,
+        open_url: F,
+    ) -> impl Future<Item = api::oauth::TokenResponse, Error = Error>
+    where
+        F: FnOnce(&str),
+    {
+        let client = self.clone();
+        let instance_url = instance_url.to_string();
+        let client_secret = app.client_secret.clone();
+
+        let request = api::oauth::AuthorizationRequest::new(
+            &app.client_id,
+            listener.redirect_uri(),
+        ).scopes(&api::oauth::Scopes::from_str(scopes));
+
+        let url = request.url(&instance_url).map(|url| open_url(&url));
+
+        url.into_future()
+            .and_then(move |_| listener.wait())
+            .and_then(move |callback| {
+                client.get_token_pkce(
+                    &instance_url,
+                    &request,
+                    &client_secret,
+                    &callback.code,
+                    &callback.state,
                 )
             })
-            .flatten_stream();
+    }
 
-        timeline::Timeline::from_lines(timeline::Lines::new(chunks))
+    /// Get a new access token using the refresh token from an earlier
+    /// `TokenResponse`.
+    pub fn refresh_token(
+        &self,
+        instance_url: &str,
+        client_id: &str,
+        client_secret: &str,
+        refresh_token: &str,
+    ) -> impl Future<Item = api::oauth::TokenResponse, Error = Error> {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "refresh_token")
+            .append_pair("client_id", client_id)
+            .append_pair("client_secret", client_secret)
+            .append_pair("refresh_token", refresh_token)
+            .finish();
+
+        self.oauth_request(instance_url, "/oauth/token", body)
+    }
+
+    /// Get an app-level access token, which isn't tied to any user but is
+    /// enough for public endpoints.
+    pub fn client_credentials_token(
+        &self,
+        instance_url: &str,
+        client_id: &str,
+        client_secret: &str,
+        scopes: &api::oauth::Scopes,
+    ) -> impl Future<Item = api::oauth::TokenResponse, Error = Error> {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "client_credentials")
+            .append_pair("client_id", client_id)
+            .append_pair("client_secret", client_secret)
+            .append_pair("scope", &scopes.0)
+            .finish();
+
+        self.oauth_request(instance_url, "/oauth/token", body)
+    }
+
+    /// Get an access token with a user's email address and password. Only
+    /// use this for your own accounts; apps acting for other users should
+    /// use the authorization code flow instead.
+    pub fn password_token(
+        &self,
+        instance_url: &str,
+        client_id: &str,
+        client_secret: &str,
+        username: &str,
+        password: &str,
+        scopes: &api::oauth::Scopes,
+    ) -> impl Future<Item = api::oauth::TokenResponse, Error = Error> {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "password")
+            .append_pair("client_id", client_id)
+            .append_pair("client_secret", client_secret)
+            .append_pair("username", username)
+            .append_pair("password", password)
+            .append_pair("scope", &scopes.0)
+            .finish();
+
+        self.oauth_request(instance_url, "/oauth/token", body)
+    }
+
+    /// Revoke an access token, so it can no longer be used.
+    pub fn revoke_token(
+        &self,
+        instance_url: &str,
+        client_id: &str,
+        client_secret: &str,
+        access_token: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("client_id", client_id)
+            .append_pair("client_secret", client_secret)
+            .append_pair("token", access_token)
+            .finish();
+
+        self.oauth_request::<serde_json::Value>(instance_url, "/oauth/revoke", body)
+            .map(|_| ())
+    }
+
+    fn oauth_request<T>(
+        &self,
+        instance_url: &str,
+        path: &str,
+        body: String,
+    ) -> impl Future<Item = T, Error = Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let request_url = api_uri(instance_url, path);
+
+        self.request_json(request_url, hyper::Method::Post, |mut req| {
+            req.headers_mut().set(
+                hyper::header::ContentType::form_url_encoded(),
+            );
+            req.set_body(body);
+            req
+        })
+    }
+
+    pub fn verify_credentials(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+    ) -> impl Future<Item = api::v1::Account, Error = Error> {
+        let request_url = api_uri(instance_url, "/api/v1/accounts/verify_credentials");
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    pub fn update_credentials(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        credentials: &api::v1::UpdateCredentials,
+    ) -> impl Future<Item = api::v1::Account, Error = Error> {
+        let request_url = api_uri(instance_url, "/api/v1/accounts/update_credentials");
+        let auth = bearer(access_token);
+        let form = credentials.as_form();
+
+        self.request_json(request_url, hyper::Method::Patch, |mut req| {
+            req.headers_mut().set(auth);
+            req.headers_mut().set_raw("Content-Type", form.content_type());
+            req.set_body(form.into_body());
+            req
+        })
+    }
+
+    pub fn get_account(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Account, Error = Error> {
+        let request_url = api_uri(instance_url, &format!("/api/v1/accounts/{}", id));
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    /// Look up an account by its `username` or `username@domain` address.
+    pub fn lookup_account(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        acct: &str,
+    ) -> impl Future<Item = api::v1::Account, Error = Error> {
+        let request_url = api_uri_with_query(instance_url, "/api/v1/accounts/lookup", |query| {
+            query.append_pair("acct", acct.trim_start_matches('@'));
+        });
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    pub fn follow(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Relationship, Error = Error> {
+        let path = format!("/api/v1/accounts/{}/follow", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn unfollow(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Relationship, Error = Error> {
+        let path = format!("/api/v1/accounts/{}/unfollow", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn block(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Relationship, Error = Error> {
+        let path = format!("/api/v1/accounts/{}/block", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn unblock(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Relationship, Error = Error> {
+        let path = format!("/api/v1/accounts/{}/unblock", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    /// Mute an account, optionally also muting notifications from it. If
+    /// `duration` is set, the mute expires after that long.
+    pub fn mute(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+        notifications: bool,
+        duration: Option<Duration>,
+    ) -> impl Future<Item = api::v1::Relationship, Error = Error> {
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        form.append_pair("notifications", bool_str(notifications));
+        if let Some(duration) = duration {
+            form.append_pair("duration", &duration.as_secs().to_string());
+        }
+
+        let path = format!("/api/v1/accounts/{}/mute", id);
+        self.post_form(instance_url, access_token, &path, Some(form.finish()))
+    }
+
+    pub fn unmute(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Relationship, Error = Error> {
+        let path = format!("/api/v1/accounts/{}/unmute", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn relationships(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        ids: &[&str],
+    ) -> impl Future<Item = Vec<api::v1::Relationship>, Error = Error> {
+        let request_url = api_uri_with_query(instance_url, "/api/v1/accounts/relationships", |query| {
+            for id in ids {
+                query.append_pair("id[]", id);
+            }
+        });
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    pub fn followers(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+        paging: &page::Paging,
+    ) -> impl Future<Item = page::Page<api::v1::Account>, Error = Error> {
+        let path = format!("/api/v1/accounts/{}/followers", id);
+        self.get_page(instance_url, access_token, &path, paging)
+    }
+
+    pub fn following(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+        paging: &page::Paging,
+    ) -> impl Future<Item = page::Page<api::v1::Account>, Error = Error> {
+        let path = format!("/api/v1/accounts/{}/following", id);
+        self.get_page(instance_url, access_token, &path, paging)
+    }
+
+    fn post_form<T>(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        path: &str,
+        body: Option<String>,
+    ) -> impl Future<Item = T, Error = Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let request_url = api_uri(instance_url, path);
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Post, |mut req| {
+            req.headers_mut().set(auth);
+            if let Some(body) = body {
+                req.headers_mut().set(
+                    hyper::header::ContentType::form_url_encoded(),
+                );
+                req.set_body(body);
+            }
+            req
+        })
+    }
+
+    fn get_page<T>(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        path: &str,
+        paging: &page::Paging,
+    ) -> impl Future<Item = page::Page<T>, Error = Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let request_url = api_uri_with_query(instance_url, path, |query| {
+            paging.append_pairs(query);
+        });
+        let auth = bearer(access_token);
+
+        self.request_page(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    /// Fetch a page of notifications. If `types` is non-empty, only those
+    /// types are included, and any in `exclude_types` are left out.
+    pub fn notifications(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        paging: &page::Paging,
+        types: &[api::v1::NotificationType],
+        exclude_types: &[api::v1::NotificationType],
+    ) -> impl Future<Item = page::Page<api::v1::Notification>, Error = Error> {
+        let request_url = api_uri_with_query(instance_url, "/api/v1/notifications", |query| {
+            paging.append_pairs(query);
+            for notification_type in types {
+                query.append_pair("types[]", notification_type.as_str());
+            }
+            for notification_type in exclude_types {
+                query.append_pair("exclude_types[]", notification_type.as_str());
+            }
+        });
+        let auth = bearer(access_token);
+
+        self.request_page(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    pub fn get_notification(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Notification, Error = Error> {
+        let request_url = api_uri(instance_url, &format!("/api/v1/notifications/{}", id));
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    pub fn dismiss_notification(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        let request_url = api_uri(
+            instance_url,
+            &format!("/api/v1/notifications/{}/dismiss", id),
+        );
+        let auth = bearer(access_token);
+
+        self.request(request_url, hyper::Method::Post, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        }).map(|_| ())
+    }
+
+    pub fn clear_notifications(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        let request_url = api_uri(instance_url, "/api/v1/notifications/clear");
+        let auth = bearer(access_token);
+
+        self.request(request_url, hyper::Method::Post, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        }).map(|_| ())
+    }
+
+    pub fn post_status(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        status: &api::v1::NewStatus,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let request_url = api_uri(instance_url, "/api/v1/statuses");
+        let auth = bearer(access_token);
+        let body = status.as_form_urlencoded();
+
+        self.request_json(request_url, hyper::Method::Post, |mut req| {
+            req.headers_mut().set(auth);
+            req.headers_mut().set(
+                hyper::header::ContentType::form_url_encoded(),
+            );
+            req.set_body(body);
+            req
+        })
+    }
+
+    pub fn get_status(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let request_url = api_uri(instance_url, &format!("/api/v1/statuses/{}", id));
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    pub fn status_context(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Context, Error = Error> {
+        let request_url = api_uri(instance_url, &format!("/api/v1/statuses/{}/context", id));
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    /// Fetch a status with its context, assembled into a reply tree.
+    pub fn thread(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = thread::Thread, Error = Error> {
+        self.get_status(instance_url, access_token, id)
+            .join(self.status_context(instance_url, access_token, id))
+            .map(|(status, context)| thread::Thread::new(status, context))
+    }
+
+    pub fn delete_status(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        let request_url = api_uri(instance_url, &format!("/api/v1/statuses/{}", id));
+        let auth = bearer(access_token);
+
+        self.request(request_url, hyper::Method::Delete, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        }).map(|_| ())
+    }
+
+    pub fn upload_media(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        media: &api::v1::NewMedia,
+    ) -> impl Future<Item = api::v1::Attachment, Error = Error> {
+        let request_url = api_uri(instance_url, "/api/v1/media");
+        let auth = bearer(access_token);
+        let form = media.as_form();
+
+        self.request_json(request_url, hyper::Method::Post, |mut req| {
+            req.headers_mut().set(auth);
+            req.headers_mut().set_raw("Content-Type", form.content_type());
+            req.set_body(form.into_body());
+            req
+        })
+    }
+
+    /// Upload media using the asynchronous `/api/v2/media` endpoint. If the
+    /// server is still processing the media, its status is checked every
+    /// `poll_interval` until the attachment's URL is available, failing with
+    /// `ErrorKind::MediaProcessing` after `max_polls` checks.
+    pub fn upload_media_async(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        media: &api::v1::NewMedia,
+        poll_interval: Duration,
+        max_polls: u32,
+    ) -> impl Future<Item = api::v1::Attachment, Error = Error> {
+        let request_url = api_uri(instance_url, "/api/v2/media");
+        let auth = bearer(access_token);
+        let form = media.as_form();
+
+        let client = self.clone();
+        let instance_url = instance_url.to_string();
+        let access_token = access_token.to_string();
+
+        let upload = self.request_json(request_url, hyper::Method::Post, |mut req| {
+            req.headers_mut().set(auth);
+            req.headers_mut().set_raw("Content-Type", form.content_type());
+            req.set_body(form.into_body());
+            req
+        });
+
+        upload.and_then(move |attachment| {
+            poll_media(attachment, max_polls, move |attachment| {
+                let client = client.clone();
+                let instance_url = instance_url.clone();
+                let access_token = access_token.clone();
+
+                client.sleep(poll_interval).and_then(move |_| {
+                    client.get_media(&instance_url, &access_token, &attachment.id)
+                })
+            })
+        })
+    }
+
+    pub fn get_media(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Attachment, Error = Error> {
+        let request_url = api_uri(instance_url, &format!("/api/v1/media/{}", id));
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    pub fn favourite(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/favourite", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn unfavourite(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/unfavourite", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    /// Reblog a status. The returned status is the reblog itself, with the
+    /// original status in its `reblog` field.
+    pub fn reblog(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+        visibility: Option<api::v1::Visibility>,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/reblog", id);
+        let body = visibility.map(|visibility| {
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("visibility", visibility.as_str())
+                .finish()
+        });
+
+        self.post_form(instance_url, access_token, &path, body)
+    }
+
+    pub fn unreblog(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/unreblog", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn bookmark(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/bookmark", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn unbookmark(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/unbookmark", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    /// Pin one of your own statuses to your profile.
+    pub fn pin(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/pin", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn unpin(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/unpin", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    /// Stop receiving notifications for the thread containing a status.
+    pub fn mute_conversation(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/mute", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn unmute_conversation(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+    ) -> impl Future<Item = api::v1::Status, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/unmute", id);
+        self.post_form(instance_url, access_token, &path, None)
+    }
+
+    pub fn favourited_by(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+        paging: &page::Paging,
+    ) -> impl Future<Item = page::Page<api::v1::Account>, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/favourited_by", id);
+        self.get_page(instance_url, access_token, &path, paging)
+    }
+
+    pub fn reblogged_by(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        id: &str,
+        paging: &page::Paging,
+    ) -> impl Future<Item = page::Page<api::v1::Account>, Error = Error> {
+        let path = format!("/api/v1/statuses/{}/reblogged_by", id);
+        self.get_page(instance_url, access_token, &path, paging)
+    }
+
+    /// Search for accounts, statuses and hashtags. With `resolve`, this also
+    /// turns a remote `@user@domain` or status URL into local IDs.
+    pub fn search(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        query: &str,
+        options: &api::v2::SearchOptions,
+    ) -> impl Future<Item = api::v2::SearchResults, Error = Error> {
+        let request_url = api_uri_with_query(instance_url, "/api/v2/search", |q| {
+            q.append_pair("q", query);
+            options.append_pairs(q);
+        });
+        let auth = bearer(access_token);
+
+        self.request_json(request_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(auth);
+            req
+        })
+    }
+
+    pub fn timeline_page(
+        &self,
+        instance_url: &str,
+        access_token: &str,
+        feed: &timeline::Feed,
+        paging: &page::Paging,
+    ) -> impl Future<Item = page::Page<api::v1::Status>, Error = Error> {
+        self.get_page(instance_url, access_token, &feed.as_path(), paging)
+    }
+
+    /// Fetch statuses from a REST timeline, starting at `paging` and
+    /// continuing through older pages until the timeline is exhausted.
+    pub fn timeline_history<I, T>(
+        &self,
+        instance_url: I,
+        access_token: T,
+        feed: timeline::Feed,
+        paging: page::Paging,
+    ) -> impl Stream<Item = api::v1::Status, Error = Error>
+    where
+        I: Into<String>,
+        T: Into<String>,
+    {
+        let client = self.clone();
+        let instance_url = instance_url.into();
+        let access_token = access_token.into();
+
+        page::Pages::new(paging, move |paging| {
+            client.timeline_page(&instance_url, &access_token, &feed, &paging)
+        })
+    }
+
+    /// Base URL of the instance's streaming API, which some instances serve
+    /// from a different host. This is looked up from `/api/v2/instance`, or
+    /// `/api/v1/instance` on older servers, the first time and cached, with clones of the client sharing the
+    /// cache. If the lookup fails, the instance URL itself is used, and the
+    /// lookup is retried after `STREAMING_URL_RETRY`.
+    pub fn streaming_url(&self, instance_url: &str) -> impl Future<Item = String, Error = Error> {
+        let instance_url = instance_url.trim_end_matches('/').to_string();
+
+        match self.streaming_urls.borrow().get(&instance_url) {
+            Some(&(ref url, None)) => return future::Either::A(future::ok(url.clone())),
+            Some(&(ref url, Some(retry_at))) if Instant::now() < retry_at => {
+                return future::Either::A(future::ok(url.clone()));
+            }
+            _ => {}
+        }
+
+        let streaming_urls = self.streaming_urls.clone();
+
+        let client = self.clone();
+        let fallback_url = instance_url.clone();
+        let lookup = self.request_json(
+            api_uri(&instance_url, "/api/v2/instance"),
+            hyper::Method::Get,
+            |req| req,
+        ).or_else(move |err| match err.class() {
+            Some(ErrorClass::NotFound) => {
+                future::Either::A(client.request_json(
+                    api_uri(&fallback_url, "/api/v1/instance"),
+                    hyper::Method::Get,
+                    |req| req,
+                ))
+            }
+            _ => future::Either::B(future::err(err)),
+        });
+
+        future::Either::B(lookup.then(move |result: Result<instance::StreamingApi>| {
+            let cached = match result {
+                Ok(streaming_api) => {
+                    let url = match streaming_api.url() {
+                        Some(url) => http_url(&url),
+                        None => instance_url.clone(),
+                    };
+                    (url, None)
+                }
+                Err(_) => (instance_url.clone(), Some(Instant::now() + STREAMING_URL_RETRY)),
+            };
+
+            let url = cached.0.clone();
+            streaming_urls.borrow_mut().insert(instance_url, cached);
+
+            Ok(url)
+        }))
+    }
+
+    /// Use `streaming_url` for all streaming connections to `instance_url`,
+    /// instead of the one advertised by the instance.
+    pub fn set_streaming_url(&self, instance_url: &str, streaming_url: &str) {
+        self.streaming_urls.borrow_mut().insert(
+            instance_url.trim_end_matches('/').to_string(),
+            (http_url(streaming_url), None),
+        );
+    }
+
+    /// Stream events from `endpoint`, connecting to the streaming host that
+    /// the instance advertises (see `streaming_url`).
+    pub fn timeline<S>(
+        &self,
+        instance_url: &str,
+        access_token: S,
+        endpoint: timeline::Endpoint,
+    ) -> impl Stream<Item = timeline::Event, Error = Error>
+    where
+        S: Into<String>,
+    {
+        let client = self.clone();
+        let access_token = access_token.into();
+
+        self.streaming_url(instance_url)
+            .map(move |streaming_url| {
+                client.streaming_timeline(&streaming_url, access_token, endpoint)
+            })
+            .flatten_stream()
+    }
+
+    /// Like `timeline`, but connects to `streaming_url` as given, without
+    /// looking up the instance's streaming host.
+    pub fn streaming_timeline<S>(
+        &self,
+        streaming_url: &str,
+        access_token: S,
+        endpoint: timeline::Endpoint,
+    ) -> impl Stream<Item = timeline::Event, Error = Error>
+    where
+        S: Into<String>,
+    {
+        let request_url = format!("{}{}", streaming_url, endpoint.as_path());
+
+        let parsed_url = request_url.parse().chain_err(|| {
+            ErrorKind::Uri(request_url.to_string())
+        });
+
+        let chunks = self.request(parsed_url, hyper::Method::Get, |mut req| {
+            req.headers_mut().set(hyper::header::Authorization(
+                hyper::header::Bearer { token: access_token.into() },
+            ));
+            req
+        }).map(|res| {
+                res.body().then(
+                    |result| result.chain_err(|| ErrorKind::Http),
+                )
+            })
+            .flatten_stream();
+
+        timeline::Timeline::from_lines(timeline::Lines::new(chunks))
+    }
+
+    /// Like `timeline`, but reopens the connection with exponential backoff
+    /// whenever it drops, emitting `Disconnected` and `Reconnected` events.
+    /// After reconnecting, statuses that were missed in the meantime are
+    /// fetched from the matching REST timeline and emitted first.
+    pub fn reconnecting_timeline<I, T>(
+        &self,
+        instance_url: I,
+        access_token: T,
+        endpoint: timeline::Endpoint,
+        backoff: reconnect::Backoff,
+    ) -> impl Stream<Item = reconnect::Event, Error = Error>
+    where
+        I: Into<String>,
+        T: Into<String>,
+    {
+        let client = self.clone();
+        let instance_url = instance_url.into();
+        let access_token = access_token.into();
+
+        let fetch = endpoint.feed().map(|feed| {
+            let client = client.clone();
+            let instance_url = instance_url.clone();
+            let access_token = access_token.clone();
+
+            move |paging| client.timeline_page(&instance_url, &access_token, &feed, &paging)
+        });
+
+        let connect = move || {
+            client.timeline(&instance_url, access_token.clone(), endpoint.clone())
+        };
+
+        future::result(self.handle())
+            .map(move |handle| {
+                let stream = reconnect::Reconnect::new(connect, handle, backoff);
+                reconnect::Backfill::new(stream, fetch)
+            })
+            .flatten_stream()
+    }
+
+    /// Open a single WebSocket streaming connection, subscribed to each of
+    /// the given endpoints. Events are paired with the endpoint they were
+    /// received on, and the returned `Subscriptions` can be used to subscribe
+    /// and unsubscribe while the connection is open.
+    pub fn websocket_timeline<S>(
+        &self,
+        instance_url: &str,
+        access_token: S,
+        endpoints: &[timeline::Endpoint],
+    ) -> (
+        websocket::Subscriptions,
+        impl Stream<Item = (timeline::Endpoint, timeline::Event), Error = Error>,
+    )
+    where
+        S: Into<String>,
+    {
+        let (subscriptions, commands) = websocket::Subscriptions::new();
+
+        let subscribed = endpoints
+            .iter()
+            .map(|endpoint| subscriptions.subscribe(endpoint))
+            .collect::<Result<Vec<()>>>();
+
+        let urls = self.streaming_url(instance_url).and_then(|streaming_url| {
+            let base_url = format!("{}/api/v1/streaming", streaming_url);
+
+            url::Url::parse(&base_url)
+                .chain_err(|| ErrorKind::Uri(base_url.to_string()))
+                .and_then(|url| {
+                    url.as_str()
+                        .parse::<hyper::Uri>()
+                        .chain_err(|| ErrorKind::Uri(url.to_string()))
+                        .map(|uri| (url, uri))
+                })
+        });
+
+        let authorization = format!("Bearer {}", access_token.into());
+        let user_agent = self.user_agent.to_string();
+
+        let connector = self.connector();
+
+        let events = subscribed
+            .into_future()
+            .and_then(move |_| connector)
+            .and_then(move |connector| urls.map(|(url, uri)| (connector, url, uri)))
+            .and_then(move |(connector, url, uri)| {
+                connector
+                    .call(uri)
+                    .then(|r| r.chain_err(|| ErrorKind::Io))
+                    .and_then(move |io| {
+                        let headers = [
+                            ("Authorization", authorization.as_str()),
+                            ("User-Agent", user_agent.as_str()),
+                        ];
+                        websocket::WebSocket::new(io, &url, &headers, commands)
+                    })
+            })
+            .flatten_stream()
+            .and_then(|text| websocket::parse_message(&text));
+
+        (subscriptions, events)
+    }
+}
+
+/// Fetch `attachment` again with `check` until its URL is available, giving
+/// up after `max_polls` checks.
+fn poll_media<F, R>(
+    attachment: api::v1::Attachment,
+    max_polls: u32,
+    mut check: F,
+) -> impl Future<Item = api::v1::Attachment, Error = Error>
+where
+    F: FnMut(api::v1::Attachment) -> R,
+    R: Future<Item = api::v1::Attachment, Error = Error>,
+{
+    future::loop_fn((attachment, 0), move |(attachment, polls)| {
+        if attachment.url.is_some() {
+            return future::Either::A(future::ok(future::Loop::Break(attachment)));
+        }
+
+        if polls >= max_polls {
+            return future::Either::A(future::err(
+                ErrorKind::MediaProcessing(attachment.id).into(),
+            ));
+        }
+
+        let check = check(attachment).map(move |attachment| {
+            future::Loop::Continue((attachment, polls + 1))
+        });
+
+        future::Either::B(check)
+    })
+}
+
+fn api_uri(instance_url: &str, path: &str) -> Result<hyper::Uri> {
+    let request_url = format!("{}{}", instance_url, path);
+    request_url.parse().chain_err(|| ErrorKind::Uri(request_url))
+}
+
+fn api_uri_with_query<F>(instance_url: &str, path: &str, modify_query: F) -> Result<hyper::Uri>
+where
+    F: FnOnce(&mut url::form_urlencoded::Serializer<url::UrlQuery>),
+{
+    let base_url = format!("{}{}", instance_url, path);
+
+    url::Url::parse(&base_url)
+        .chain_err(|| ErrorKind::Uri(base_url.to_string()))
+        .and_then(|mut url| {
+            modify_query(&mut url.query_pairs_mut());
+
+            if url.query() == Some("") {
+                url.set_query(None);
+            }
+
+            url.as_str().parse::<hyper::Uri>().chain_err(|| {
+                ErrorKind::Uri(url.into_string())
+            })
+        })
+}
+
+/// Streaming URLs are advertised as WebSocket URLs, but the connectors only
+/// know about HTTP schemes.
+fn http_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+
+    if let Some(rest) = url.strip_prefix("wss://") {
+        format!("https://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("ws://") {
+        format!("http://{}", rest)
+    } else {
+        url.to_string()
+    }
+}
+
+fn rate_limit_key(
+    uri: &hyper::Uri,
+    auth: Option<&hyper::header::Authorization<hyper::header::Bearer>>,
+) -> RateLimitKey {
+    let instance = format!(
+        "{}://{}",
+        uri.scheme().unwrap_or("https"),
+        uri.authority().unwrap_or("")
+    );
+
+    (instance, auth.map(|auth| auth.0.token.clone()))
+}
+
+/// Form and query parameter value for a boolean.
+fn bool_str(value: bool) -> &'static str {
+    if value { "true" } else { "false" }
+}
+
+fn bearer(access_token: &str) -> hyper::header::Authorization<hyper::header::Bearer> {
+    hyper::header::Authorization(hyper::header::Bearer { token: access_token.to_string() })
+}
+
+fn json_body<T>(res: hyper::Response) -> impl Future<Item = T, Error = Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    res.body()
+        .concat2()
+        .then(|r| r.chain_err(|| ErrorKind::Http))
+        .and_then(|bytes| {
+            serde_json::from_slice(&bytes).chain_err(|| {
+                let invalid_json = String::from_utf8_lossy(&bytes);
+                ErrorKind::Deserialize(invalid_json.into())
+            })
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use tokio_core::reactor::Core;
+
+    fn attachment(url: Option<&str>) -> api::v1::Attachment {
+        api::v1::Attachment {
+            id: "22345792".to_string(),
+            media_type: api::v1::MediaType::Image,
+            url: url.map(|url| url.to_string()),
+            remote_url: None,
+            preview_url: "https://files.example.com/small/image.png".to_string(),
+            text_url: None,
+            meta: None,
+            description: None,
+            blurhash: None,
+        }
+    }
+
+    #[test]
+    fn poll_media_until_processed() {
+        let checks = Cell::new(0);
+        let processed = poll_media(attachment(None), 5, |_| {
+            checks.set(checks.get() + 1);
+            let url = if checks.get() == 3 {
+                Some("https://files.example.com/original/image.png")
+            } else {
+                None
+            };
+            future::ok(attachment(url))
+        }).wait()
+            .unwrap();
+
+        assert_eq!(checks.get(), 3);
+        assert!(processed.url.is_some());
+    }
+
+    #[test]
+    fn poll_media_gives_up() {
+        let checks = Cell::new(0);
+        let result = poll_media(attachment(None), 5, |_| {
+            checks.set(checks.get() + 1);
+            future::ok(attachment(None))
+        }).wait();
+
+        assert_eq!(checks.get(), 5);
+        match result {
+            Err(Error(ErrorKind::MediaProcessing(ref id), _)) => assert_eq!(id, "22345792"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sleep_without_handle() {
+        let core = Core::new().unwrap();
+        let handle = core.handle();
+        let connector = HttpsConnector::new(1, &handle).unwrap();
+        let hyper = hyper::Client::configure().connector(connector).build(&handle);
+        let client = Client::from_hyper_client(hyper, "test");
+
+        assert!(client.sleep(Duration::from_secs(0)).wait().is_ok());
+        match client.sleep(Duration::from_secs(1)).wait() {
+            Err(Error(ErrorKind::MissingHandle, _)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 }
@@ -0,0 +1,290 @@
+//! A local HTTP listener that receives the OAuth redirect after the user
+//! authorizes an app in their browser, as an alternative to copying and
+//! pasting out-of-band codes.
+
+use api::oauth::{CreateAppResponse, TokenResponse};
+use error::*;
+use futures::{Future, Stream, future};
+use futures::future::Either;
+use futures::unsync::mpsc;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_io;
+use url;
+
+const CALLBACK_PATH: &str = "/callback";
+
+/// How long each connection has to send its request before it is dropped.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The result of `Client::loopback_login`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Login {
+    /// The app that was registered, which is needed to refresh or revoke
+    /// the token later.
+    pub app: CreateAppResponse,
+    pub token: TokenResponse,
+}
+
+/// The query parameters the browser was redirected back with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Callback {
+    pub code: String,
+    pub state: String,
+}
+
+pub struct Listener {
+    listener: TcpListener,
+    handle: Handle,
+    redirect_uri: String,
+}
+
+impl Listener {
+    /// Listen on a random free port on `127.0.0.1`.
+    pub fn bind(handle: &Handle) -> Result<Self> {
+        Listener::bind_port(handle, 0)
+    }
+
+    /// Listen on a specific port on `127.0.0.1`, such as the one in the
+    /// redirect URI of an app that was registered earlier.
+    pub fn bind_port(handle: &Handle, port: u16) -> Result<Self> {
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let listener = TcpListener::bind(&addr, handle).chain_err(|| ErrorKind::Io)?;
+        let port = listener.local_addr().chain_err(|| ErrorKind::Io)?.port();
+
+        Ok(Listener {
+            listener,
+            handle: handle.clone(),
+            redirect_uri: format!("http://127.0.0.1:{}{}", port, CALLBACK_PATH),
+        })
+    }
+
+    /// The URI to register the app with, and to send in the authorization
+    /// request.
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    /// Wait for the browser to be redirected back. Other requests, such as
+    /// for `/favicon.ico`, get a 404 and are otherwise ignored.
+    ///
+    /// Connections are handled concurrently, and each is dropped if it
+    /// hasn't sent its request within `CONNECTION_TIMEOUT`, so idle
+    /// connections that browsers open ahead of time can't hold up the
+    /// callback. This still waits indefinitely if the user never finishes
+    /// authorizing, so combine it with a `Timeout` if needed.
+    pub fn wait(self) -> impl Future<Item = Callback, Error = Error> {
+        let (sender, receiver) = mpsc::unbounded();
+        let handle = self.handle;
+
+        let accept = self.listener.incoming().for_each(move |(stream, _)| {
+            let sender = sender.clone();
+            let timeout = Timeout::new(CONNECTION_TIMEOUT, &handle)?;
+
+            // A single broken connection shouldn't end the wait
+            let connection = respond(stream).select2(timeout).then(move |result| {
+                if let Ok(Either::A((Some(callback), _))) = result {
+                    let _ = sender.unbounded_send(callback);
+                }
+                Ok(())
+            });
+
+            handle.spawn(connection);
+            Ok(())
+        });
+
+        let accept = accept
+            .then(|r| r.chain_err(|| ErrorKind::Io))
+            .and_then(|_| Err(ErrorKind::Io.into()));
+
+        let callback = receiver.into_future().then(|result| match result {
+            Ok((Some(callback), _)) => callback,
+            _ => Err(ErrorKind::Io.into()),
+        });
+
+        callback.select(accept).map(|(callback, _)| callback).map_err(
+            |(err, _)| err,
+        )
+    }
+}
+
+fn respond(stream: TcpStream) -> impl Future<Item = Option<Result<Callback>>, Error = io::Error> {
+    read_head(BufReader::new(stream)).and_then(|(reader, request_line)| {
+        let callback = parse_request_line(&request_line);
+
+        let (status, message) = match callback {
+            Some(Ok(_)) => ("200 OK", "Authorization complete. You can close this window."),
+            Some(Err(_)) => ("200 OK", "Authorization failed. You can close this window."),
+            None => ("404 Not Found", "Not found."),
+        };
+
+        let body = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"></head><body><p>{}</p></body></html>",
+            message
+        );
+        let response = format!(
+            "HTTP/1.1 {}\r\n\
+            Content-Type: text/html; charset=utf-8\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\
+            \r\n\
+            {}",
+            status,
+            body.len(),
+            body
+        );
+
+        tokio_io::io::write_all(reader.into_inner(), response).map(move |_| callback)
+    })
+}
+
+// Read up to the end of the headers, so that closing the connection doesn't
+// reset it while the browser is still waiting for the response
+fn read_head(
+    reader: BufReader<TcpStream>,
+) -> impl Future<Item = (BufReader<TcpStream>, String), Error = io::Error> {
+    future::loop_fn((reader, None), |(reader, request_line): (_, Option<String>)| {
+        tokio_io::io::read_until(reader, b'\n', Vec::new()).map(move |(reader, line)| {
+            let line = String::from_utf8_lossy(&line).trim_end().to_string();
+
+            if line.is_empty() {
+                future::Loop::Break((reader, request_line.unwrap_or_default()))
+            } else {
+                future::Loop::Continue((reader, request_line.or(Some(line))))
+            }
+        })
+    })
+}
+
+/// The port of a redirect URI created by `Listener`, or `None` if it isn't
+/// one.
+pub fn redirect_port(redirect_uri: &str) -> Option<u16> {
+    let url = url::Url::parse(redirect_uri).ok()?;
+
+    if url.scheme() == "http" && url.host_str() == Some("127.0.0.1") &&
+        url.path() == CALLBACK_PATH
+    {
+        url.port()
+    } else {
+        None
+    }
+}
+
+/// Get the callback parameters from a request line such as
+/// `GET /callback?code=...&state=... HTTP/1.1`, or `None` if the request
+/// isn't for the callback.
+fn parse_request_line(line: &str) -> Option<Result<Callback>> {
+    let mut parts = line.split(' ');
+    let target = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => target,
+        _ => return None,
+    };
+
+    let url = url::Url::parse(&format!("http://127.0.0.1{}", target)).ok()?;
+    if url.path() != CALLBACK_PATH {
+        return None;
+    }
+
+    let param = |name| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    if let Some(error) = param("error") {
+        let reason = param("error_description").unwrap_or(error);
+        return Some(Err(ErrorKind::Authorization(reason).into()));
+    }
+
+    match (param("code"), param("state")) {
+        (Some(code), Some(state)) => Some(Ok(Callback { code, state })),
+        _ => Some(Err(
+            ErrorKind::Authorization("missing code or state".to_string()).into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net;
+    use std::thread;
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn parse_callback() {
+        assert_eq!(
+            parse_request_line("GET /callback?code=abc&state=x%2By HTTP/1.1").unwrap().unwrap(),
+            Callback {
+                code: "abc".to_string(),
+                state: "x+y".to_string(),
+            }
+        );
+
+        let denied = parse_request_line(
+            "GET /callback?error=access_denied&error_description=Denied HTTP/1.1",
+        );
+        match *denied.unwrap().unwrap_err().kind() {
+            ErrorKind::Authorization(ref reason) => assert_eq!(reason, "Denied"),
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+
+        assert!(parse_request_line("GET /callback?code=abc HTTP/1.1").unwrap().is_err());
+        assert!(parse_request_line("GET /favicon.ico HTTP/1.1").is_none());
+        assert!(parse_request_line("POST /callback?code=abc&state=x HTTP/1.1").is_none());
+    }
+
+    #[test]
+    fn redirect_uri_port() {
+        assert_eq!(redirect_port("http://127.0.0.1:8080/callback"), Some(8080));
+        assert_eq!(redirect_port("http://localhost:8080/callback"), None);
+        assert_eq!(redirect_port("https://example.com/callback"), None);
+        assert_eq!(redirect_port("urn:ietf:wg:oauth:2.0:oob"), None);
+    }
+
+    #[test]
+    fn listener() {
+        let mut core = Core::new().unwrap();
+        let listener = Listener::bind(&core.handle()).unwrap();
+        let redirect_uri = listener.redirect_uri().to_string();
+        assert!(redirect_uri.starts_with("http://127.0.0.1:"));
+
+        let addr = redirect_uri["http://".len()..redirect_uri.len() - CALLBACK_PATH.len()]
+            .parse::<SocketAddr>()
+            .unwrap();
+
+        let browser = thread::spawn(move || {
+            // Browsers may open connections they don't use straight away
+            let _idle = net::TcpStream::connect(addr).unwrap();
+
+            let mut responses = Vec::new();
+            for path in &["/favicon.ico", "/callback?code=abc&state=xyz"] {
+                let mut stream = net::TcpStream::connect(addr).unwrap();
+                write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr).unwrap();
+
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                responses.push(response);
+            }
+            responses
+        });
+
+        let callback = core.run(listener.wait()).unwrap();
+        assert_eq!(
+            callback,
+            Callback {
+                code: "abc".to_string(),
+                state: "xyz".to_string(),
+            }
+        );
+
+        let responses = browser.join().unwrap();
+        assert!(responses[0].starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(responses[1].starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(responses[1].contains("Authorization complete"));
+    }
+}
@@ -0,0 +1,126 @@
+//! Encoder for `multipart/form-data` request bodies.
+
+use rand::{self, Rng};
+
+#[derive(Clone, Debug)]
+pub struct Form {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Form::new()
+    }
+}
+
+impl Form {
+    pub fn new() -> Self {
+        let boundary = rand::thread_rng()
+            .gen_ascii_chars()
+            .take(32)
+            .collect::<String>();
+
+        Form::with_boundary(format!("olifants-{}", boundary))
+    }
+
+    /// Create a form with a fixed boundary. The boundary must not appear
+    /// anywhere in the form's contents.
+    pub fn with_boundary<S: Into<String>>(boundary: S) -> Self {
+        Form {
+            boundary: boundary.into(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        self.start_part(name, None, None);
+        self.body.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    pub fn file(mut self, name: &str, file_name: &str, content_type: &str, data: &[u8]) -> Self {
+        self.start_part(name, Some(file_name), Some(content_type));
+        self.body.extend_from_slice(data);
+        self
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Value for the `Content-Type` header of the request.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    pub fn into_body(mut self) -> Vec<u8> {
+        if !self.body.is_empty() {
+            self.body.extend_from_slice(b"\r\n");
+        }
+        self.body.extend_from_slice(b"--");
+        self.body.extend_from_slice(self.boundary.as_bytes());
+        self.body.extend_from_slice(b"--\r\n");
+        self.body
+    }
+
+    fn start_part(&mut self, name: &str, file_name: Option<&str>, content_type: Option<&str>) {
+        if !self.body.is_empty() {
+            self.body.extend_from_slice(b"\r\n");
+        }
+
+        let mut headers = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape(name)
+        );
+
+        if let Some(file_name) = file_name {
+            headers.push_str(&format!("; filename=\"{}\"", escape(file_name)));
+        }
+        headers.push_str("\r\n");
+
+        if let Some(content_type) = content_type {
+            headers.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        headers.push_str("\r\n");
+
+        self.body.extend_from_slice(headers.as_bytes());
+    }
+}
+
+// Quotes and line breaks would end the header value early
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn form_body() {
+        let form = Form::with_boundary("XYZ")
+            .text("description", "a \"cat\"")
+            .file("file", "cat.png", "image/png", b"\x89PNG");
+
+        assert_eq!(form.content_type(), "multipart/form-data; boundary=XYZ");
+        assert_eq!(
+            form.into_body(),
+            b"--XYZ\r\n\
+            Content-Disposition: form-data; name=\"description\"\r\n\
+            \r\n\
+            a \"cat\"\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"cat.png\"\r\n\
+            Content-Type: image/png\r\n\
+            \r\n\
+            \x89PNG\r\n\
+            --XYZ--\r\n"
+                .to_vec()
+        );
+    }
+}
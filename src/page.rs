@@ -0,0 +1,262 @@
+use error::*;
+use futures::{Async, Future, IntoFuture, Stream};
+use hyper::header::{Link, RelationType};
+use std::vec;
+use url;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Paging {
+    pub max_id: Option<String>,
+    pub since_id: Option<String>,
+    pub min_id: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl Paging {
+    pub fn new() -> Self {
+        Paging::default()
+    }
+
+    pub fn max_id<S: Into<String>>(mut self, max_id: S) -> Self {
+        self.max_id = Some(max_id.into());
+        self
+    }
+
+    pub fn since_id<S: Into<String>>(mut self, since_id: S) -> Self {
+        self.since_id = Some(since_id.into());
+        self
+    }
+
+    pub fn min_id<S: Into<String>>(mut self, min_id: S) -> Self {
+        self.min_id = Some(min_id.into());
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn append_pairs<T>(&self, query: &mut url::form_urlencoded::Serializer<T>)
+    where
+        T: url::form_urlencoded::Target,
+    {
+        if let Some(ref max_id) = self.max_id {
+            query.append_pair("max_id", max_id);
+        }
+        if let Some(ref since_id) = self.since_id {
+            query.append_pair("since_id", since_id);
+        }
+        if let Some(ref min_id) = self.min_id {
+            query.append_pair("min_id", min_id);
+        }
+        if let Some(limit) = self.limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub max_id: Option<String>,
+    pub since_id: Option<String>,
+    pub min_id: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Build a page from a response body and its `Link` header. The `next`
+    /// link carries the `max_id` cursor, and the `prev` link carries
+    /// `since_id` and/or `min_id`.
+    pub fn new(items: Vec<T>, link: Option<&Link>) -> Self {
+        let mut page = Page {
+            items,
+            max_id: None,
+            since_id: None,
+            min_id: None,
+        };
+
+        let values = link.map(|l| l.values()).unwrap_or(&[]);
+
+        for value in values {
+            let rel = value.rel().unwrap_or(&[]);
+            let is_next = rel.contains(&RelationType::Next);
+            let is_prev = rel.contains(&RelationType::Prev);
+
+            let url = match url::Url::parse(value.link()) {
+                Ok(url) => url,
+                Err(_) => continue,
+            };
+
+            for (key, value) in url.query_pairs() {
+                match key.as_ref() {
+                    "max_id" if is_next => page.max_id = Some(value.into_owned()),
+                    "since_id" if is_prev => page.since_id = Some(value.into_owned()),
+                    "min_id" if is_prev => page.min_id = Some(value.into_owned()),
+                    _ => {}
+                }
+            }
+        }
+
+        page
+    }
+
+    /// Paging parameters for the page immediately older than this one.
+    pub fn older(&self) -> Option<Paging> {
+        self.max_id.as_ref().map(|max_id| Paging::new().max_id(max_id.as_str()))
+    }
+
+    /// Paging parameters for the page immediately newer than this one.
+    pub fn newer(&self) -> Option<Paging> {
+        let min_id = self.min_id.as_ref().map(|min_id| Paging::new().min_id(min_id.as_str()));
+        let since_id = self.since_id.as_ref().map(|since_id| {
+            Paging::new().since_id(since_id.as_str())
+        });
+
+        min_id.or(since_id)
+    }
+}
+
+/// Stream that yields every item from a page, then fetches the next older
+/// page, until the server returns an empty page or no `next` link.
+#[must_use = "streams do nothing unless polled"]
+pub struct Pages<T, F, R>
+where
+    R: IntoFuture,
+{
+    fetch: F,
+    limit: Option<u32>,
+    next: Option<Paging>,
+    pending: Option<R::Future>,
+    items: vec::IntoIter<T>,
+}
+
+impl<T, F, R> Pages<T, F, R>
+where
+    F: FnMut(Paging) -> R,
+    R: IntoFuture<Item = Page<T>, Error = Error>,
+{
+    pub fn new(paging: Paging, fetch: F) -> Self {
+        Pages {
+            fetch,
+            limit: paging.limit,
+            next: Some(paging),
+            pending: None,
+            items: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<T, F, R> Stream for Pages<T, F, R>
+where
+    F: FnMut(Paging) -> R,
+    R: IntoFuture<Item = Page<T>, Error = Error>,
+{
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Self::Item>>> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Ok(Async::Ready(Some(item)));
+            }
+
+            let polled = match self.pending {
+                Some(ref mut future) => future.poll(),
+                None => {
+                    match self.next.take() {
+                        Some(paging) => {
+                            self.pending = Some((self.fetch)(paging).into_future());
+                            continue;
+                        }
+                        None => return Ok(Async::Ready(None)),
+                    }
+                }
+            };
+
+            let page = match polled {
+                Ok(Async::Ready(page)) => page,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => {
+                    self.pending = None;
+                    return Err(e);
+                }
+            };
+
+            self.pending = None;
+            self.next = if page.items.is_empty() {
+                None
+            } else {
+                page.older().map(|paging| Paging {
+                    limit: self.limit,
+                    ..paging
+                })
+            };
+            self.items = page.items.into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::future;
+
+    #[test]
+    fn page_cursors_from_link() {
+        let link = "<https://example.com/api/v1/timelines/home?max_id=100>; rel=\"next\", \
+                    <https://example.com/api/v1/timelines/home?min_id=200>; rel=\"prev\""
+            .parse::<Link>()
+            .unwrap();
+
+        let page = Page::new(vec![1, 2, 3], Some(&link));
+        assert_eq!(page.max_id, Some("100".to_string()));
+        assert_eq!(page.min_id, Some("200".to_string()));
+        assert_eq!(page.since_id, None);
+        assert_eq!(page.older(), Some(Paging::new().max_id("100")));
+        assert_eq!(page.newer(), Some(Paging::new().min_id("200")));
+
+        let empty = Page::new(Vec::<i32>::new(), None);
+        assert_eq!(empty.older(), None);
+        assert_eq!(empty.newer(), None);
+    }
+
+    #[test]
+    fn pages_until_exhausted() {
+        let mut requested = Vec::new();
+
+        let items = Pages::new(Paging::new().limit(2), |paging: Paging| {
+            requested.push(paging.clone());
+
+            let page = match paging.max_id {
+                None => page_of(vec![5, 4], Some("4")),
+                Some(ref id) if id == "4" => page_of(vec![3, 2], Some("2")),
+                Some(ref id) if id == "2" => page_of(vec![1], None),
+                ref other => panic!("unexpected max_id {:?}", other),
+            };
+
+            future::ok(page)
+        }).collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(items, vec![5, 4, 3, 2, 1]);
+        assert_eq!(
+            requested,
+            vec![
+                Paging::new().limit(2),
+                Paging::new().max_id("4").limit(2),
+                Paging::new().max_id("2").limit(2),
+            ]
+        );
+    }
+
+    fn page_of(items: Vec<i32>, max_id: Option<&str>) -> Page<i32> {
+        Page {
+            items,
+            max_id: max_id.map(|s| s.to_string()),
+            since_id: None,
+            min_id: None,
+        }
+    }
+}
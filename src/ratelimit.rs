@@ -0,0 +1,138 @@
+//! Rate limits reported by the server in `X-RateLimit-*` response headers.
+
+use chrono::{DateTime, TimeZone, Utc};
+use hyper;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Number of requests allowed in the current window.
+    pub limit: u32,
+    pub remaining: u32,
+    /// When the window ends and `remaining` goes back to `limit`.
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    /// Read the rate limit from response headers, if all three are present.
+    pub fn from_headers(headers: &hyper::Headers) -> Option<Self> {
+        let header = |name| {
+            headers
+                .get_raw(name)
+                .and_then(|raw| raw.one())
+                .and_then(|bytes| ::std::str::from_utf8(bytes).ok())
+                .map(str::trim)
+        };
+
+        Some(RateLimit {
+            limit: header("X-RateLimit-Limit")?.parse().ok()?,
+            remaining: header("X-RateLimit-Remaining")?.parse().ok()?,
+            reset: parse_reset(header("X-RateLimit-Reset")?)?,
+        })
+    }
+
+    /// Time left until the window resets, or zero if it already has.
+    pub fn until_reset(&self, now: DateTime<Utc>) -> Duration {
+        (self.reset - now).to_std().unwrap_or_default()
+    }
+}
+
+// Mastodon sends an ISO 8601 timestamp, but some other servers send seconds
+// since the epoch
+fn parse_reset(value: &str) -> Option<DateTime<Utc>> {
+    value
+        .parse::<DateTime<Utc>>()
+        .ok()
+        .or_else(|| value.parse().ok().and_then(|secs| Utc.timestamp_opt(secs, 0).single()))
+}
+
+/// Settings for automatic throttling, which is off unless enabled with
+/// `Client::throttle`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Throttle {
+    /// Once this few requests remain, wait for the window to reset before
+    /// sending more.
+    pub min_remaining: u32,
+    /// How many times to retry a request that got `429 Too Many Requests`.
+    pub max_retries: u32,
+    /// How long to wait before retrying a `429` response that doesn't say
+    /// when the window resets.
+    pub retry_delay: Duration,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Throttle {
+            min_remaining: 5,
+            max_retries: 3,
+            retry_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Throttle {
+    /// How long to hold a request back, given the last known rate limit.
+    pub fn delay(&self, rate_limit: &RateLimit, now: DateTime<Utc>) -> Duration {
+        if rate_limit.remaining <= self.min_remaining {
+            rate_limit.until_reset(now)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    /// How long to wait before retrying a rate limited request.
+    pub fn retry_delay(&self, rate_limit: Option<&RateLimit>, now: DateTime<Utc>) -> Duration {
+        match rate_limit {
+            Some(rate_limit) if rate_limit.reset > now => rate_limit.until_reset(now),
+            _ => self.retry_delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers(reset: &str) -> hyper::Headers {
+        let mut headers = hyper::Headers::new();
+        headers.set_raw("X-RateLimit-Limit", "300");
+        headers.set_raw("X-RateLimit-Remaining", "4");
+        headers.set_raw("X-RateLimit-Reset", reset.to_string());
+        headers
+    }
+
+    #[test]
+    fn parse_headers() {
+        let rate_limit = RateLimit::from_headers(&headers("2017-04-12T19:05:00.491380Z")).unwrap();
+        assert_eq!(rate_limit.limit, 300);
+        assert_eq!(rate_limit.remaining, 4);
+        assert_eq!(
+            rate_limit.reset,
+            Utc.with_ymd_and_hms(2017, 4, 12, 19, 5, 0).unwrap() +
+                ::chrono::Duration::microseconds(491_380)
+        );
+
+        let rate_limit = RateLimit::from_headers(&headers("1492023900")).unwrap();
+        assert_eq!(rate_limit.reset, Utc.with_ymd_and_hms(2017, 4, 12, 19, 5, 0).unwrap());
+
+        assert_eq!(RateLimit::from_headers(&hyper::Headers::new()), None);
+        assert_eq!(RateLimit::from_headers(&headers("soon")), None);
+    }
+
+    #[test]
+    fn throttle_delay() {
+        let now = Utc.with_ymd_and_hms(2017, 4, 12, 19, 0, 0).unwrap();
+        let throttle = Throttle::default();
+        let mut rate_limit = RateLimit::from_headers(&headers("1492023900")).unwrap();
+
+        assert_eq!(throttle.delay(&rate_limit, now), Duration::from_secs(300));
+        rate_limit.remaining = 100;
+        assert_eq!(throttle.delay(&rate_limit, now), Duration::from_secs(0));
+
+        assert_eq!(
+            throttle.retry_delay(Some(&rate_limit), now),
+            Duration::from_secs(300)
+        );
+        assert_eq!(throttle.retry_delay(None, now), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,460 @@
+use api;
+use error::*;
+use futures::{Async, Future, IntoFuture, Stream};
+use page::{Page, Paging};
+use rand::{self, Rng};
+use std::collections::{HashSet, VecDeque};
+use std::mem;
+use std::time::Duration;
+use timeline;
+use tokio_core::reactor::{Handle, Timeout};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: u32,
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            multiplier: 2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the given reconnection attempt (starting at 1), without
+    /// jitter applied.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let mut delay = self.initial;
+
+        for _ in 1..attempt {
+            delay = delay.checked_mul(self.multiplier).unwrap_or(self.max);
+            if delay >= self.max {
+                break;
+            }
+        }
+
+        ::std::cmp::min(delay, self.max)
+    }
+
+    /// Delay with "equal jitter" applied, i.e., a random duration between
+    /// half of the delay and the full delay.
+    pub fn delay_with_jitter(&self, attempt: u32) -> Duration {
+        let delay = self.delay(attempt);
+        let millis = delay.as_secs() * 1000 + u64::from(delay.subsec_millis());
+
+        if millis < 2 {
+            return delay;
+        }
+
+        let jittered = rand::thread_rng().gen_range(millis / 2, millis + 1);
+        Duration::from_millis(jittered)
+    }
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Timeline(timeline::Event),
+    /// The connection was lost, either because the stream ended or because
+    /// of the contained error.
+    Disconnected(Option<Error>),
+    /// The stream is receiving data again after the given number of attempts.
+    Reconnected(u32),
+}
+
+enum State<S> {
+    Connected(S),
+    Waiting(Timeout),
+}
+
+#[must_use = "streams do nothing unless polled"]
+pub struct Reconnect<F, S> {
+    connect: F,
+    handle: Handle,
+    backoff: Backoff,
+    state: State<S>,
+    attempts: u32,
+    buffered: Option<timeline::Event>,
+}
+
+impl<F, S> Reconnect<F, S>
+where
+    F: FnMut() -> S,
+    S: Stream<Item = timeline::Event, Error = Error>,
+{
+    pub fn new(mut connect: F, handle: Handle, backoff: Backoff) -> Self {
+        let stream = connect();
+
+        Reconnect {
+            connect,
+            handle,
+            backoff,
+            state: State::Connected(stream),
+            attempts: 0,
+            buffered: None,
+        }
+    }
+
+    fn schedule_reconnect(&mut self, error: Option<Error>) -> Result<Option<Event>> {
+        let was_connected = self.attempts == 0;
+        self.attempts += 1;
+
+        if let Some(max_attempts) = self.backoff.max_attempts {
+            if self.attempts > max_attempts {
+                let kind = ErrorKind::Reconnect(max_attempts);
+                return Err(match error {
+                    Some(e) => Error::with_chain(e, kind),
+                    None => kind.into(),
+                });
+            }
+        }
+
+        let delay = self.backoff.delay_with_jitter(self.attempts);
+        let timeout = Timeout::new(delay, &self.handle).chain_err(|| ErrorKind::Timer)?;
+        self.state = State::Waiting(timeout);
+
+        if was_connected {
+            Ok(Some(Event::Disconnected(error)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<F, S> Stream for Reconnect<F, S>
+where
+    F: FnMut() -> S,
+    S: Stream<Item = timeline::Event, Error = Error>,
+{
+    type Item = Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Self::Item>>> {
+        if let Some(event) = self.buffered.take() {
+            return Ok(Async::Ready(Some(Event::Timeline(event))));
+        }
+
+        loop {
+            let disconnected = match self.state {
+                State::Connected(ref mut stream) => {
+                    match stream.poll() {
+                        Ok(Async::Ready(Some(event))) => {
+                            if self.attempts == 0 {
+                                return Ok(Async::Ready(Some(Event::Timeline(event))));
+                            }
+
+                            // First data since reconnecting
+                            let attempts = self.attempts;
+                            self.attempts = 0;
+                            self.buffered = Some(event);
+                            return Ok(Async::Ready(Some(Event::Reconnected(attempts))));
+                        }
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(None)) => None,
+                        Err(e) => Some(e),
+                    }
+                }
+                State::Waiting(ref mut timeout) => {
+                    try_ready!(timeout.poll().chain_err(|| ErrorKind::Timer));
+                    self.state = State::Connected((self.connect)());
+                    continue;
+                }
+            };
+
+            if let Some(event) = self.schedule_reconnect(disconnected)? {
+                return Ok(Async::Ready(Some(event)));
+            }
+        }
+    }
+}
+
+/// Number of recent status IDs remembered for dropping duplicates.
+const SEEN_CAPACITY: usize = 1024;
+
+/// Wraps a reconnecting stream, and after each reconnect, fetches whatever
+/// statuses were missed using `since_id` on the REST timeline. Missed
+/// statuses are emitted oldest first, before live events resume, and any
+/// status that was already emitted is dropped.
+#[must_use = "streams do nothing unless polled"]
+pub struct Backfill<S, F, R>
+where
+    R: IntoFuture,
+{
+    stream: S,
+    fetch: Option<F>,
+    last_id: Option<api::v1::StatusId>,
+    since_id: Option<api::v1::StatusId>,
+    seen: VecDeque<api::v1::StatusId>,
+    seen_set: HashSet<api::v1::StatusId>,
+    pending: Option<R::Future>,
+    fetched: Vec<api::v1::Status>,
+    ready: VecDeque<api::v1::Status>,
+}
+
+impl<S, F, R> Backfill<S, F, R>
+where
+    S: Stream<Item = Event, Error = Error>,
+    F: FnMut(Paging) -> R,
+    R: IntoFuture<Item = Page<api::v1::Status>, Error = Error>,
+{
+    /// If `fetch` is `None`, no backfilling happens, but duplicate updates
+    /// are still dropped.
+    pub fn new(stream: S, fetch: Option<F>) -> Self {
+        Backfill {
+            stream,
+            fetch,
+            last_id: None,
+            since_id: None,
+            seen: VecDeque::with_capacity(SEEN_CAPACITY),
+            seen_set: HashSet::with_capacity(SEEN_CAPACITY),
+            pending: None,
+            fetched: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    // Returns `false` if the ID was already seen
+    fn remember(&mut self, id: &api::v1::StatusId) -> bool {
+        if self.seen_set.contains(id) {
+            return false;
+        }
+
+        if self.seen.len() >= SEEN_CAPACITY {
+            if let Some(oldest) = self.seen.pop_front() {
+                self.seen_set.remove(&oldest);
+            }
+        }
+
+        self.seen.push_back(id.clone());
+        self.seen_set.insert(id.clone());
+        self.last_id = Some(id.clone());
+        true
+    }
+
+    fn fetch(&mut self, paging: Paging) {
+        if let Some(ref mut fetch) = self.fetch {
+            self.pending = Some(fetch(paging).into_future());
+        }
+    }
+}
+
+impl<S, F, R> Stream for Backfill<S, F, R>
+where
+    S: Stream<Item = Event, Error = Error>,
+    F: FnMut(Paging) -> R,
+    R: IntoFuture<Item = Page<api::v1::Status>, Error = Error>,
+{
+    type Item = Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Self::Item>>> {
+        loop {
+            if let Some(status) = self.ready.pop_front() {
+                let event = timeline::Event::Update(Box::new(status));
+                return Ok(Async::Ready(Some(Event::Timeline(event))));
+            }
+
+            let polled = self.pending.as_mut().map(|future| future.poll());
+
+            match polled {
+                Some(Ok(Async::Ready(page))) => {
+                    self.pending = None;
+
+                    let older = if page.items.is_empty() {
+                        None
+                    } else {
+                        page.older()
+                    };
+                    self.fetched.extend(page.items);
+
+                    if let Some(paging) = older {
+                        let since_id = self.since_id.clone();
+                        self.fetch(Paging { since_id, ..paging });
+                        continue;
+                    }
+
+                    // Pages are returned newest first
+                    let fetched = mem::take(&mut self.fetched);
+                    for status in fetched.into_iter().rev() {
+                        if self.remember(&status.id) {
+                            self.ready.push_back(status);
+                        }
+                    }
+                    continue;
+                }
+                Some(Ok(Async::NotReady)) => return Ok(Async::NotReady),
+                Some(Err(e)) => {
+                    self.pending = None;
+                    self.fetched.clear();
+                    return Err(e);
+                }
+                None => {}
+            }
+
+            match try_ready!(self.stream.poll()) {
+                Some(Event::Timeline(timeline::Event::Update(status))) => {
+                    if self.remember(&status.id) {
+                        let event = timeline::Event::Update(status);
+                        return Ok(Async::Ready(Some(Event::Timeline(event))));
+                    }
+                }
+                Some(Event::Reconnected(attempts)) => {
+                    if let Some(since_id) = self.last_id.clone() {
+                        self.since_id = Some(since_id.clone());
+                        self.fetch(Paging::new().since_id(since_id));
+                    }
+                    return Ok(Async::Ready(Some(Event::Reconnected(attempts))));
+                }
+                other => return Ok(Async::Ready(other)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixtures::status;
+    use futures::{future, stream};
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn backoff_delay() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+            multiplier: 3,
+            max_attempts: None,
+        };
+
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(300));
+        assert_eq!(backoff.delay(3), Duration::from_millis(900));
+        assert_eq!(backoff.delay(4), Duration::from_millis(1000));
+        assert_eq!(backoff.delay(100), Duration::from_millis(1000));
+
+        for attempt in 1..10 {
+            let jittered = backoff.delay_with_jitter(attempt);
+            assert!(jittered <= backoff.delay(attempt));
+            assert!(jittered >= backoff.delay(attempt) / 2);
+        }
+    }
+
+    #[test]
+    fn reconnect_until_max_attempts() {
+        let mut core = Core::new().unwrap();
+
+        let backoff = Backoff {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(1),
+            multiplier: 1,
+            max_attempts: Some(2),
+        };
+
+        let mut connections = 0;
+        let connect = || {
+            connections += 1;
+            let events = match connections {
+                1 | 3 => vec![Ok(timeline::Event::Heartbeat)],
+                2 => vec![Err(ErrorKind::Http.into())],
+                _ => vec![],
+            };
+            stream::iter_result(events)
+        };
+
+        let reconnect = Reconnect::new(connect, core.handle(), backoff);
+        let mut events = Vec::new();
+        let result = core.run(reconnect.for_each(|event| {
+            events.push(format!("{:?}", event));
+            Ok(())
+        }));
+
+        match result.unwrap_err().kind() {
+            &ErrorKind::Reconnect(2) => {}
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                "Timeline(Heartbeat)",
+                "Disconnected(None)",
+                "Reconnected(2)",
+                "Timeline(Heartbeat)",
+                "Disconnected(None)",
+            ]
+        );
+    }
+
+    #[test]
+    fn backfill_after_reconnect() {
+        let update = |id| Event::Timeline(timeline::Event::Update(Box::new(status(id))));
+
+        let events = stream::iter_ok(vec![
+            update("1"),
+            update("2"),
+            Event::Disconnected(None),
+            Event::Reconnected(1),
+            update("4"),
+            update("5"),
+            update("6"),
+        ]);
+
+        let mut requested = Vec::new();
+        let fetch = |paging: Paging| {
+            requested.push(paging.clone());
+
+            let (ids, max_id) = match paging.max_id {
+                None => (vec!["5", "4"], Some("4")),
+                Some(ref id) if id == "4" => (vec!["3"], Some("3")),
+                Some(_) => (vec![], None),
+            };
+
+            future::ok(Page {
+                items: ids.into_iter().map(status).collect(),
+                max_id: max_id.map(|s| s.to_string()),
+                since_id: None,
+                min_id: None,
+            })
+        };
+
+        let ids = Backfill::new(events, Some(fetch))
+            .map(|event| match event {
+                Event::Timeline(timeline::Event::Update(status)) => status.id,
+                other => format!("{:?}", other),
+            })
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            ids,
+            vec![
+                "1",
+                "2",
+                "Disconnected(None)",
+                "Reconnected(1)",
+                "3",
+                "4",
+                "5",
+                "6",
+            ]
+        );
+
+        assert_eq!(
+            requested,
+            vec![
+                Paging::new().since_id("2"),
+                Paging::new().since_id("2").max_id("4"),
+                Paging::new().since_id("2").max_id("3"),
+            ]
+        );
+    }
+}
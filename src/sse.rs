@@ -0,0 +1,192 @@
+//! Decoder for the `text/event-stream` format, following the [WHATWG
+//! specification](https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation).
+
+use error::*;
+use futures::{Async, Stream};
+use std::mem;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    /// Event type, which defaults to `message` if the event has no `event`
+    /// field.
+    pub event: String,
+    /// Contents of all `data` fields, joined by newlines.
+    pub data: String,
+    /// The last event ID seen on the stream, as of this event.
+    pub id: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Event(Event),
+    /// A line starting with `:`, with the colon removed. These are usually
+    /// sent to keep the connection alive.
+    Comment(String),
+}
+
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct Decoder<S> {
+    lines: S,
+    started: bool,
+    event: String,
+    data: String,
+    last_event_id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl<S> Decoder<S>
+where
+    S: Stream<Item = String, Error = Error>,
+{
+    /// Decode events from a stream of lines, with line endings removed.
+    pub fn new(lines: S) -> Self {
+        Decoder {
+            lines,
+            started: false,
+            event: String::new(),
+            data: String::new(),
+            last_event_id: None,
+            retry: None,
+        }
+    }
+
+    /// The reconnection time in milliseconds requested by the server.
+    pub fn retry(&self) -> Option<u64> {
+        self.retry
+    }
+
+    fn process_field(&mut self, field: &str, value: &str) {
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                self.retry = value.parse().ok();
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = mem::take(&mut self.event);
+        let mut data = mem::take(&mut self.data);
+
+        if data.is_empty() {
+            return None;
+        }
+
+        data.pop(); // Remove trailing newline
+
+        Some(Event {
+            event: if event.is_empty() {
+                "message".to_string()
+            } else {
+                event
+            },
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+impl<S> Stream for Decoder<S>
+where
+    S: Stream<Item = String, Error = Error>,
+{
+    type Item = Message;
+    type Error = Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Self::Item>>> {
+        loop {
+            let mut line = match try_ready!(self.lines.poll()) {
+                Some(line) => line,
+                None => return Ok(Async::Ready(None)), // Incomplete events are discarded
+            };
+
+            if !self.started {
+                self.started = true;
+                if line.starts_with('\u{feff}') {
+                    line.remove(0);
+                }
+            }
+
+            if line.is_empty() {
+                if let Some(event) = self.dispatch() {
+                    return Ok(Async::Ready(Some(Message::Event(event))));
+                }
+            } else if let Some(comment) = line.strip_prefix(':') {
+                return Ok(Async::Ready(Some(Message::Comment(comment.to_string()))));
+            } else if let Some(index) = line.find(':') {
+                let (field, value) = line.split_at(index);
+                let value = &value[1..];
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                self.process_field(field, value);
+            } else {
+                self.process_field(&line, "");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{Future, stream};
+
+    fn decode(lines: &[&str]) -> Vec<Message> {
+        let lines = lines.iter().map(|line| Ok(line.to_string())).collect::<Vec<_>>();
+        Decoder::new(stream::iter_result(lines))
+            .collect()
+            .wait()
+            .unwrap()
+    }
+
+    fn event(event: &str, data: &str, id: Option<&str>) -> Message {
+        Message::Event(Event {
+            event: event.to_string(),
+            data: data.to_string(),
+            id: id.map(|s| s.to_string()),
+        })
+    }
+
+    #[test]
+    fn multiline_data() {
+        let messages = decode(&["event: update", "data: {\"a\":", "data:1}", "", ""]);
+        assert_eq!(messages, vec![event("update", "{\"a\":\n1}", None)]);
+    }
+
+    #[test]
+    fn comments_and_fields() {
+        let messages = decode(&[
+            "\u{feff}:thump",
+            "retry: 1000",
+            "id: 1",
+            "data",
+            "",
+            "event:delete",
+            "data:  123",
+            "unknown: field",
+            "",
+            "data: incomplete",
+        ]);
+
+        assert_eq!(
+            messages,
+            vec![
+                Message::Comment("thump".to_string()),
+                event("message", "", Some("1")),
+                event("delete", " 123", Some("1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_data_is_not_dispatched() {
+        let messages = decode(&["event: update", "", "data: hi", ""]);
+        assert_eq!(messages, vec![event("message", "hi", None)]);
+    }
+}
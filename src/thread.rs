@@ -0,0 +1,272 @@
+//! Reply trees assembled from a status and its `Context`.
+
+use api::v1::{Context, Status};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Thread {
+    statuses: Vec<Status>,
+    index: HashMap<String, usize>,
+    parents: Vec<Option<usize>>,
+    replies: Vec<Vec<usize>>,
+    root: usize,
+}
+
+impl Thread {
+    /// Build the tree for `status` from its context, linking each status to
+    /// the one named by its `in_reply_to_id`.
+    ///
+    /// Statuses the server didn't return, because they were deleted or aren't
+    /// visible, leave gaps in the chain. Anything whose parent is missing is
+    /// attached to the root instead. Replies can also form a cycle, which is
+    /// broken at its earliest status by attaching that to the root, so every
+    /// status remains reachable.
+    pub fn new(status: Status, context: Context) -> Self {
+        let mut statuses = context.ancestors;
+        statuses.push(status);
+        statuses.extend(context.descendants);
+
+        let index = statuses
+            .iter()
+            .enumerate()
+            .map(|(i, status)| (status.id.clone(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut parents = statuses
+            .iter()
+            .map(|status| {
+                status
+                    .in_reply_to_id
+                    .as_ref()
+                    .and_then(|id| index.get(id).cloned())
+            })
+            .collect::<Vec<_>>();
+
+        let root = parents.iter().position(Option::is_none).unwrap_or(0);
+        parents[root] = None;
+
+        let mut replies = vec![Vec::new(); statuses.len()];
+        for (i, parent) in parents.iter_mut().enumerate() {
+            if i != root {
+                let p = parent.unwrap_or(root);
+                *parent = Some(p);
+                replies[p].push(i);
+            }
+        }
+
+        let mut reachable = vec![false; statuses.len()];
+        mark_reachable(&replies, &mut reachable, root);
+
+        while let Some(mut i) = reachable.iter().position(|&r| !r) {
+            // Following parents from a status the root can't reach always
+            // ends up going around a cycle
+            let mut seen = vec![false; statuses.len()];
+            while !seen[i] {
+                seen[i] = true;
+                i = parents[i].unwrap_or(root);
+            }
+
+            let mut earliest = i;
+            let mut j = parents[i].unwrap_or(root);
+            while j != i {
+                earliest = earliest.min(j);
+                j = parents[j].unwrap_or(root);
+            }
+
+            if let Some(p) = parents[earliest] {
+                replies[p].retain(|&j| j != earliest);
+            }
+            parents[earliest] = Some(root);
+            replies[root].push(earliest);
+            replies[root].sort();
+            mark_reachable(&replies, &mut reachable, earliest);
+        }
+
+        Thread {
+            statuses,
+            index,
+            parents,
+            replies,
+            root,
+        }
+    }
+
+    /// The status that starts the thread.
+    pub fn root(&self) -> &Status {
+        &self.statuses[self.root]
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Status> {
+        self.position(id).map(|i| &self.statuses[i])
+    }
+
+    /// The status `id` is attached to in the tree, or `None` for the root.
+    pub fn parent(&self, id: &str) -> Option<&Status> {
+        self.position(id)
+            .and_then(|i| self.parents[i])
+            .map(|i| &self.statuses[i])
+    }
+
+    /// Direct replies to `id`, in chronological order.
+    pub fn replies(&self, id: &str) -> Vec<&Status> {
+        match self.position(id) {
+            Some(i) => self.replies[i].iter().map(|&j| &self.statuses[j]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Number of statuses in the thread, which is never zero.
+    pub fn len(&self) -> usize {
+        self.statuses.len()
+    }
+
+    /// Always `false`, since a thread contains at least its root.
+    pub fn is_empty(&self) -> bool {
+        self.statuses.is_empty()
+    }
+
+    /// Walk the tree depth-first from the root, yielding each status with its
+    /// depth, where the root has depth 0.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            thread: self,
+            stack: vec![(self.root, 0)],
+        }
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        self.index.get(id).cloned()
+    }
+}
+
+impl<'a> IntoIterator for &'a Thread {
+    type Item = (usize, &'a Status);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Iter<'a> {
+    thread: &'a Thread,
+    stack: Vec<(usize, usize)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (usize, &'a Status);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, depth) = self.stack.pop()?;
+        self.stack.extend(
+            self.thread.replies[i]
+                .iter()
+                .rev()
+                .map(|&j| (j, depth + 1)),
+        );
+        Some((depth, &self.thread.statuses[i]))
+    }
+}
+
+fn mark_reachable(replies: &[Vec<usize>], reachable: &mut [bool], from: usize) {
+    let mut stack = vec![from];
+    while let Some(i) = stack.pop() {
+        if !reachable[i] {
+            reachable[i] = true;
+            stack.extend(&replies[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixtures;
+
+    #[test]
+    fn build_tree() {
+        let context = Context {
+            ancestors: vec![status("1", None), status("2", Some("1"))],
+            descendants: vec![
+                status("4", Some("3")),
+                status("5", Some("2")),
+                status("6", Some("4")),
+                status("7", Some("3")),
+                status("9", Some("8")),
+            ],
+        };
+        let thread = Thread::new(status("3", Some("2")), context);
+
+        assert_eq!(thread.len(), 8);
+        assert_eq!(thread.root().id, "1");
+        assert_eq!(thread.parent("6").unwrap().id, "4");
+        assert_eq!(thread.parent("1"), None);
+        // 8 isn't in the context, so 9 hangs off the root
+        assert_eq!(thread.parent("9").unwrap().id, "1");
+
+        let replies = thread.replies("3").iter().map(|s| &s.id[..]).collect::<Vec<_>>();
+        assert_eq!(replies, vec!["4", "7"]);
+
+        let walk = thread
+            .iter()
+            .map(|(depth, s)| (depth, &s.id[..]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            walk,
+            vec![
+                (0, "1"),
+                (1, "2"),
+                (2, "3"),
+                (3, "4"),
+                (4, "6"),
+                (3, "7"),
+                (2, "5"),
+                (1, "9"),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_ancestors() {
+        // The status replies to something we can't see
+        let context = Context {
+            ancestors: vec![],
+            descendants: vec![status("2", Some("1"))],
+        };
+        let thread = Thread::new(status("1", Some("0")), context);
+
+        assert_eq!(thread.root().id, "1");
+        assert_eq!(thread.iter().count(), 2);
+    }
+
+    #[test]
+    fn reply_cycle() {
+        // 3 and 4 reply to each other, and 5 replies to 4
+        let context = Context {
+            ancestors: vec![],
+            descendants: vec![
+                status("2", Some("1")),
+                status("3", Some("4")),
+                status("4", Some("3")),
+                status("5", Some("4")),
+            ],
+        };
+        let thread = Thread::new(status("1", None), context);
+
+        assert_eq!(thread.parent("3").unwrap().id, "1");
+        assert_eq!(thread.parent("4").unwrap().id, "3");
+
+        let walk = thread
+            .iter()
+            .map(|(depth, s)| (depth, &s.id[..]))
+            .collect::<Vec<_>>();
+        assert_eq!(walk, vec![(0, "1"), (1, "2"), (1, "3"), (2, "4"), (3, "5")]);
+    }
+
+    fn status(id: &str, in_reply_to_id: Option<&str>) -> Status {
+        let mut status = fixtures::status(id);
+        status.in_reply_to_id = in_reply_to_id.map(|id| id.to_string());
+        status
+    }
+}
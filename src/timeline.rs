@@ -2,6 +2,7 @@ use api;
 use error::*;
 use futures::{Async, Stream};
 use serde_json;
+use sse;
 use std::borrow::Cow;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -38,6 +39,93 @@ impl Endpoint {
             Other(ref path) => path.clone().into(),
         }
     }
+
+    /// The stream name and parameter used to subscribe to this endpoint over
+    /// the WebSocket streaming API.
+    pub fn stream_name(&self) -> Option<(&'static str, Option<&str>)> {
+        use self::Endpoint::*;
+
+        match *self {
+            User => Some(("user", None)),
+            Notification => Some(("user:notification", None)),
+            Federated => Some(("public", None)),
+            Local => Some(("public:local", None)),
+            Hashtag(ref tag) => Some(("hashtag", Some(tag))),
+            LocalHashtag(ref tag) => Some(("hashtag:local", Some(tag))),
+            Other(_) => None,
+        }
+    }
+
+    /// The endpoint for the `stream` field of a WebSocket streaming message,
+    /// e.g., `["hashtag", "foo"]`.
+    pub fn from_stream_name(stream: &[String]) -> Endpoint {
+        use self::Endpoint::*;
+
+        let name = stream.first().map(|s| s.as_str());
+        let param = stream.get(1).cloned();
+
+        match (name, param) {
+            (Some("user"), None) => User,
+            (Some("user:notification"), None) => Notification,
+            (Some("public"), None) => Federated,
+            (Some("public:local"), None) => Local,
+            (Some("hashtag"), Some(tag)) => Hashtag(tag),
+            (Some("hashtag:local"), Some(tag)) => LocalHashtag(tag),
+            _ => Other(stream.join(":")),
+        }
+    }
+
+    /// The REST timeline that contains the same statuses as this streaming
+    /// endpoint, if there is one.
+    pub fn feed(&self) -> Option<Feed> {
+        use self::Endpoint::*;
+
+        match *self {
+            User => Some(Feed::Home),
+            Federated => Some(Feed::Public),
+            Local => Some(Feed::Local),
+            Hashtag(ref tag) => Some(Feed::Hashtag(tag.clone())),
+            LocalHashtag(ref tag) => Some(Feed::LocalHashtag(tag.clone())),
+            Notification | Other(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Feed {
+    Home,
+    Public,
+    Local,
+    Hashtag(String),
+    LocalHashtag(String),
+    List(String),
+}
+
+impl Feed {
+    pub fn as_path<'a>(&self) -> Cow<'a, str> {
+        use url::percent_encoding::{PATH_SEGMENT_ENCODE_SET, utf8_percent_encode};
+        use self::Feed::*;
+
+        match *self {
+            Home => "/api/v1/timelines/home".into(),
+            Public => "/api/v1/timelines/public".into(),
+            Local => "/api/v1/timelines/public?local=true".into(),
+
+            Hashtag(ref tag) => {
+                let encoded_tag = utf8_percent_encode(tag, PATH_SEGMENT_ENCODE_SET);
+                format!("/api/v1/timelines/tag/{}", encoded_tag).into()
+            }
+            LocalHashtag(ref tag) => {
+                let encoded_tag = utf8_percent_encode(tag, PATH_SEGMENT_ENCODE_SET);
+                format!("/api/v1/timelines/tag/{}?local=true", encoded_tag).into()
+            }
+
+            List(ref id) => {
+                let encoded_id = utf8_percent_encode(id, PATH_SEGMENT_ENCODE_SET);
+                format!("/api/v1/timelines/list/{}", encoded_id).into()
+            }
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -45,13 +133,75 @@ enum EventType {
     Update,
     Notification,
     Delete,
+    FiltersChanged,
+    Conversation,
+    Announcement,
+    AnnouncementReaction,
+    AnnouncementDelete,
+    StatusUpdate,
+    EncryptedMessage,
+}
+
+impl EventType {
+    fn from_name(name: &str) -> Option<EventType> {
+        use self::EventType::*;
+
+        match name {
+            "update" => Some(Update),
+            "delete" => Some(Delete),
+            "notification" => Some(Notification),
+            "filters_changed" => Some(FiltersChanged),
+            "conversation" => Some(Conversation),
+            "announcement" => Some(Announcement),
+            "announcement.reaction" => Some(AnnouncementReaction),
+            "announcement.delete" => Some(AnnouncementDelete),
+            "status.update" => Some(StatusUpdate),
+            "encrypted_message" => Some(EncryptedMessage),
+            _ => None,
+        }
+    }
+
+    fn parse(self, data: &str) -> Result<Event> {
+        use self::EventType::*;
+
+        match self {
+            Update => json(data).map(|status| Event::Update(Box::new(status))),
+            Notification => {
+                json(data).map(|notification| Event::Notification(Box::new(notification)))
+            }
+            Delete => {
+                data.parse::<api::v1::StatusId>()
+                    .chain_err(|| ErrorKind::StatusId(data.to_string()))
+                    .map(Event::Delete)
+            }
+            FiltersChanged => Ok(Event::FiltersChanged),
+            Conversation => {
+                json(data).map(|conversation| Event::Conversation(Box::new(conversation)))
+            }
+            Announcement => {
+                json(data).map(|announcement| Event::Announcement(Box::new(announcement)))
+            }
+            AnnouncementReaction => json(data).map(Event::AnnouncementReaction),
+            AnnouncementDelete => Ok(Event::AnnouncementDelete(data.to_string())),
+            StatusUpdate => json(data).map(|status| Event::StatusUpdate(Box::new(status))),
+            EncryptedMessage => {
+                json(data).map(|message| Event::EncryptedMessage(Box::new(message)))
+            }
+        }
+    }
+}
+
+fn json<T>(data: &str) -> Result<T>
+where
+    T: ::serde::de::DeserializeOwned,
+{
+    serde_json::from_str(data).chain_err(|| ErrorKind::Deserialize(data.to_string()))
 }
 
 #[derive(Debug)]
 #[must_use = "streams do nothing unless polled"]
 pub struct Timeline<S> {
-    lines: S,
-    waiting_for: Option<EventType>,
+    messages: sse::Decoder<S>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize)]
@@ -59,18 +209,40 @@ pub enum Event {
     Update(Box<api::v1::Status>),
     Notification(Box<api::v1::Notification>),
     Delete(api::v1::StatusId),
+    FiltersChanged,
+    Conversation(Box<api::v1::Conversation>),
+    Announcement(Box<api::v1::Announcement>),
+    AnnouncementReaction(api::v1::AnnouncementReaction),
+    AnnouncementDelete(api::v1::AnnouncementId),
+    /// A status was edited.
+    StatusUpdate(Box<api::v1::Status>),
+    EncryptedMessage(Box<api::v1::EncryptedMessage>),
+    /// An event type this crate doesn't know about, with its raw data.
+    Unknown { event: String, data: String },
     Heartbeat,
 }
 
+impl Event {
+    /// Parse the data of a streaming API event, given the event's name.
+    pub fn parse(event_type: &str, data: &str) -> Result<Event> {
+        match EventType::from_name(event_type) {
+            Some(t) => t.parse(data),
+            None => {
+                Ok(Event::Unknown {
+                    event: event_type.to_string(),
+                    data: data.to_string(),
+                })
+            }
+        }
+    }
+}
+
 impl<S> Timeline<S>
 where
     S: Stream<Item = String, Error = Error>,
 {
     pub fn from_lines(lines: S) -> Timeline<S> {
-        Timeline {
-            lines,
-            waiting_for: None,
-        }
+        Timeline { messages: sse::Decoder::new(lines) }
     }
 }
 
@@ -82,65 +254,12 @@ where
     type Error = Error;
 
     fn poll(&mut self) -> Result<Async<Option<Self::Item>>> {
-        use self::EventType::*;
-
-        loop {
-            if let Some(line) = try_ready!(self.lines.poll()) {
-                if line.starts_with(':') {
-                    return Ok(Async::Ready(Some(Event::Heartbeat)));
-                }
-
-                if let Some(event_type) = self.waiting_for {
-                    if line.starts_with("data: ") {
-                        let data = &line[6..];
-
-                        self.waiting_for = None;
-
-                        match event_type {
-                            Update => {
-                                return serde_json::from_str(data).chain_err(|| {
-                                    ErrorKind::Deserialize(data.to_string())
-                                }).map(|status| {
-                                    Async::Ready(Some(Event::Update(Box::new(status))))
-                                })
-                            }
-                            Notification => {
-                                return serde_json::from_str(data)
-                                    .chain_err(|| ErrorKind::Deserialize(data.to_string()))
-                                    .map(|notification| {
-                                        Async::Ready(
-                                            Some(Event::Notification(Box::new(notification))),
-                                        )
-                                    })
-                            }
-                            Delete => {
-                                return data.parse::<api::v1::StatusId>()
-                                    .chain_err(|| ErrorKind::StatusId(data.to_string()))
-                                    .map(|id| Async::Ready(Some(Event::Delete(id))))
-                            }
-                        }
-                    } else {
-                        // We're in an unexpected state, reset to be safe
-                        self.waiting_for = None;
-                        bail!(ErrorKind::StreamingState("data", line));
-                    }
-                } else if line.starts_with("event: ") {
-                    let event_type = match &line[7..] {
-                        "update" => Update,
-                        "delete" => Delete,
-                        "notification" => Notification,
-                        other => {
-                            bail!(ErrorKind::EventType(other.to_string()));
-                        }
-                    };
-
-                    self.waiting_for = Some(event_type);
-                } else if !line.is_empty() {
-                    bail!(ErrorKind::StreamingState("event", line));
-                }
-            } else {
-                return Ok(Async::Ready(None));
+        match try_ready!(self.messages.poll()) {
+            Some(sse::Message::Comment(_)) => Ok(Async::Ready(Some(Event::Heartbeat))),
+            Some(sse::Message::Event(event)) => {
+                Event::parse(&event.event, &event.data).map(|e| Async::Ready(Some(e)))
             }
+            None => Ok(Async::Ready(None)),
         }
     }
 }
@@ -150,6 +269,7 @@ where
 pub struct Lines<S> {
     stream: S,
     buffer: Vec<u8>,
+    after_cr: bool,
 }
 
 impl<S, B> Lines<S>
@@ -161,6 +281,7 @@ where
         Lines {
             stream,
             buffer: Vec::new(),
+            after_cr: false,
         }
     }
 }
@@ -175,18 +296,26 @@ where
 
     fn poll(&mut self) -> Result<Async<Option<Self::Item>>> {
         loop {
-            if let Some(index) = self.buffer.iter().position(|c| *c == b'\n') {
-                // Buffer contains a newline, split the buffer and return
+            // A `\r\n` line ending may be split across chunks
+            if self.after_cr && !self.buffer.is_empty() {
+                if self.buffer[0] == b'\n' {
+                    self.buffer.remove(0);
+                }
+                self.after_cr = false;
+            }
+
+            if let Some(index) = self.buffer.iter().position(|c| *c == b'\n' || *c == b'\r') {
+                // Buffer contains a line ending, split the buffer and return
                 let mut split = self.buffer.split_off(index + 1);
                 ::std::mem::swap(&mut self.buffer, &mut split);
-                split.pop(); // Remove trailing newline
+                self.after_cr = split.pop() == Some(b'\r'); // Remove line ending
 
                 return String::from_utf8(split)
                     .map(|line| Async::Ready(Some(line)))
                     .chain_err(|| ErrorKind::Utf8);
             } else if let Some(chunk) = try_ready!(self.stream.poll()) {
                 // No newline in current chunk, attempt to fill the buffer
-                self.buffer.extend_from_slice(&chunk.as_ref());
+                self.buffer.extend_from_slice(chunk.as_ref());
             } else {
                 // Underlying stream is finished
                 return Ok(Async::Ready(None));
@@ -213,12 +342,64 @@ mod test {
         assert!(path.parse::<::hyper::Uri>().is_ok());
     }
 
+    #[test]
+    fn feed_encoding() {
+        assert_eq!(Feed::Local.as_path(), "/api/v1/timelines/public?local=true");
+
+        let path = Feed::Hashtag("a/b c".into()).as_path();
+        assert_eq!(path, "/api/v1/timelines/tag/a%2Fb%20c");
+        assert!(path.parse::<::hyper::Uri>().is_ok());
+    }
+
+    #[test]
+    fn timeline_events() {
+        let lines = vec![
+            ":)",
+            "event: delete",
+            "data:123",
+            "",
+            "event: delete",
+        ];
+        let lines = futures::stream::iter_ok(lines.into_iter().map(|line| line.to_string()));
+
+        let events = Timeline::from_lines(lines).collect().wait().unwrap();
+        assert_eq!(events, vec![Event::Heartbeat, Event::Delete("123".to_string())]);
+    }
+
+    #[test]
+    fn newer_event_types() {
+        assert_eq!(
+            Event::parse("filters_changed", "undefined").unwrap(),
+            Event::FiltersChanged
+        );
+        assert_eq!(
+            Event::parse("announcement.delete", "5").unwrap(),
+            Event::AnnouncementDelete("5".to_string())
+        );
+        assert_eq!(
+            Event::parse("announcement.reaction", r#"{"name":"👍","count":2,"announcement_id":"5"}"#)
+                .unwrap(),
+            Event::AnnouncementReaction(api::v1::AnnouncementReaction {
+                name: "👍".to_string(),
+                count: 2,
+                announcement_id: "5".to_string(),
+            })
+        );
+        assert_eq!(
+            Event::parse("something.new", "{}").unwrap(),
+            Event::Unknown {
+                event: "something.new".to_string(),
+                data: "{}".to_string(),
+            }
+        );
+    }
+
     #[test]
     fn lines() {
         let (msg_tx, msg_rx) = mpsc::unbounded::<&[u8]>();
         let mut lines = Lines::new(msg_rx.map_err(|_| Error::from_kind(ErrorKind::Http)));
 
-        let send = move |msg| mpsc::UnboundedSender::send(&msg_tx, msg);
+        let send = move |msg| msg_tx.unbounded_send(msg);
         let mut expect = |value| assert_eq!(lines.poll().unwrap(), value);
 
         // Run on a task context
@@ -246,6 +427,15 @@ mod test {
             send(cool2);
             expect(Async::Ready(Some("🆒".to_string())));
 
+            // Lines can end with `\r\n`, `\r` or `\n`
+            send("CRLF\r".as_bytes());
+            expect(Async::Ready(Some("CRLF".to_string())));
+            expect(Async::NotReady);
+            send("\nCR\rLF\n\r\n".as_bytes());
+            expect(Async::Ready(Some("CR".to_string())));
+            expect(Async::Ready(Some("LF".to_string())));
+            expect(Async::Ready(Some("".to_string())));
+
             Ok::<(), ()>(())
         }).wait()
             .unwrap();
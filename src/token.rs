@@ -0,0 +1,235 @@
+//! Access tokens that keep track of when they expire.
+
+use Client;
+use api::oauth::TokenResponse;
+use chrono::{self, DateTime, TimeZone, Utc};
+use connector::HttpsConnector;
+use error::*;
+use futures::{Future, future};
+use futures::future::Shared;
+use hyper::client::Connect;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Token {
+    pub response: TokenResponse,
+    /// When the access token expires, or `None` if it doesn't.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Token {
+    /// Wrap a token, taking its issue time from `created_at`, or assuming it
+    /// was just issued if the server didn't say.
+    pub fn new(response: TokenResponse) -> Self {
+        let issued_at = response
+            .created_at
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+            .unwrap_or_else(Utc::now);
+
+        Token::issued_at(response, issued_at)
+    }
+
+    pub fn issued_at(response: TokenResponse, issued_at: DateTime<Utc>) -> Self {
+        let expires_at = response
+            .expires_in
+            .map(|secs| issued_at + chrono::Duration::seconds(secs as i64));
+
+        Token {
+            response,
+            expires_at,
+        }
+    }
+
+    pub fn access_token(&self) -> &str {
+        &self.response.access_token
+    }
+
+    /// Whether the token will have expired `margin` after `now`.
+    pub fn expires_within(&self, margin: Duration, now: DateTime<Utc>) -> bool {
+        let margin = chrono::Duration::from_std(margin).unwrap_or_else(|_| chrono::Duration::zero());
+
+        match self.expires_at {
+            Some(expires_at) => expires_at <= now + margin,
+            None => false,
+        }
+    }
+}
+
+/// A refresh that is in progress, shared by every caller waiting for it.
+type Refresh = Shared<Box<dyn Future<Item = String, Error = Error>>>;
+
+/// A token that is refreshed shortly before it expires, using its refresh
+/// token. Clones share the same token.
+#[derive(Clone)]
+pub struct RefreshingToken<H = HttpsConnector> {
+    client: Client<H>,
+    instance_url: String,
+    client_id: String,
+    client_secret: String,
+    token: Rc<RefCell<Token>>,
+    refreshing: Rc<RefCell<Option<Refresh>>>,
+    margin: Duration,
+}
+
+impl<H> RefreshingToken<H>
+where
+    H: Connect + Clone,
+{
+    pub fn new<I, C, S>(
+        client: Client<H>,
+        instance_url: I,
+        client_id: C,
+        client_secret: S,
+        token: Token,
+    ) -> Self
+    where
+        I: Into<String>,
+        C: Into<String>,
+        S: Into<String>,
+    {
+        RefreshingToken {
+            client,
+            instance_url: instance_url.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            token: Rc::new(RefCell::new(token)),
+            refreshing: Rc::default(),
+            margin: Duration::from_secs(60),
+        }
+    }
+
+    /// Refresh the token once it is this close to expiring. Defaults to one
+    /// minute.
+    pub fn margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// The current token, which may have been refreshed since this was
+    /// created.
+    pub fn token(&self) -> Token {
+        self.token.borrow().clone()
+    }
+
+    /// Get an access token that won't expire within the margin, refreshing
+    /// it first if needed. Tokens without a refresh token are returned as
+    /// they are, even if they have expired. Callers that ask while a refresh
+    /// is in progress wait for it instead of sending another.
+    pub fn access_token(&self) -> impl Future<Item = String, Error = Error> {
+        let token = self.token();
+
+        let refresh_token = match token.response.refresh_token {
+            Some(ref refresh_token) if token.expires_within(self.margin, Utc::now()) => {
+                refresh_token.clone()
+            }
+            _ => return future::Either::A(future::ok(token.response.access_token)),
+        };
+
+        let in_flight = self.refreshing.borrow().clone();
+        let refresh = match in_flight {
+            Some(refresh) => refresh,
+            None => {
+                let refresh = self.refresh(refresh_token);
+                *self.refreshing.borrow_mut() = Some(refresh.clone());
+                refresh
+            }
+        };
+
+        let refreshed = refresh
+            .map(|access_token| (*access_token).clone())
+            .map_err(|err| shared_error(&err));
+
+        future::Either::B(refreshed)
+    }
+
+    fn refresh(&self, refresh_token: String) -> Refresh {
+        let shared = self.token.clone();
+        let refreshing = self.refreshing.clone();
+
+        let refreshed = self.client
+            .refresh_token(
+                &self.instance_url,
+                &self.client_id,
+                &self.client_secret,
+                &refresh_token,
+            )
+            .then(move |result| {
+                *refreshing.borrow_mut() = None;
+
+                result.map(|mut response| {
+                    // Servers that don't rotate refresh tokens leave it out
+                    if response.refresh_token.is_none() {
+                        response.refresh_token = Some(refresh_token);
+                    }
+
+                    let token = Token::new(response);
+                    let access_token = token.access_token().to_string();
+                    *shared.borrow_mut() = token;
+                    access_token
+                })
+            });
+
+        let refreshed: Box<dyn Future<Item = String, Error = Error>> = Box::new(refreshed);
+        refreshed.shared()
+    }
+}
+
+/// Errors can't be cloned, so each caller waiting on a failed refresh gets a
+/// copy of the server's error response, or a description of any other error.
+fn shared_error(err: &Error) -> Error {
+    match *err.kind() {
+        ErrorKind::Api(ref error) => ErrorKind::Api(error.clone()).into(),
+        _ => ErrorKind::Authorization(format!("could not refresh token: {}", err)).into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(expires_in: Option<u64>) -> TokenResponse {
+        TokenResponse {
+            access_token: "token".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            token_type: "Bearer".to_string(),
+            expires_in,
+            scope: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn expiry() {
+        let issued_at = Utc.with_ymd_and_hms(2017, 4, 12, 19, 0, 0).unwrap();
+        let token = Token::issued_at(response(Some(3600)), issued_at);
+
+        assert_eq!(
+            token.expires_at,
+            Some(Utc.with_ymd_and_hms(2017, 4, 12, 20, 0, 0).unwrap())
+        );
+
+        let margin = Duration::from_secs(60);
+        let later = |mins| issued_at + chrono::Duration::minutes(mins);
+        assert!(!token.expires_within(margin, later(58)));
+        assert!(token.expires_within(margin, later(59)));
+        assert!(token.expires_within(margin, later(120)));
+
+        let token = Token::issued_at(response(None), issued_at);
+        assert_eq!(token.expires_at, None);
+        assert!(!token.expires_within(margin, later(120)));
+    }
+
+    #[test]
+    fn expiry_from_created_at() {
+        let mut stored = response(Some(3600));
+        stored.created_at = Some(Utc.with_ymd_and_hms(2017, 4, 12, 19, 0, 0).unwrap().timestamp());
+        let token = Token::new(stored);
+
+        assert_eq!(
+            token.expires_at,
+            Some(Utc.with_ymd_and_hms(2017, 4, 12, 20, 0, 0).unwrap())
+        );
+    }
+}
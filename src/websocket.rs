@@ -0,0 +1,714 @@
+use base64;
+use error::*;
+use futures::{Async, Stream};
+use futures::unsync::mpsc;
+use rand::{self, Rng};
+use serde_json;
+use sha1;
+use std::io;
+use timeline::{Endpoint, Event};
+use tokio_io::{AsyncRead, AsyncWrite};
+use url;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Largest frame payload, and largest message assembled from fragments,
+/// that is accepted from the server.
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Handle for changing the streams a WebSocket connection is subscribed to,
+/// while the connection is open.
+#[derive(Clone, Debug)]
+pub struct Subscriptions {
+    commands: mpsc::UnboundedSender<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Command<'a> {
+    #[serde(rename = "type")]
+    command_type: &'static str,
+    stream: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    #[serde(default)]
+    stream: Vec<String>,
+    event: String,
+    payload: Option<serde_json::Value>,
+}
+
+impl Subscriptions {
+    pub fn new() -> (Subscriptions, mpsc::UnboundedReceiver<String>) {
+        let (commands, receiver) = mpsc::unbounded();
+        (Subscriptions { commands }, receiver)
+    }
+
+    pub fn subscribe(&self, endpoint: &Endpoint) -> Result<()> {
+        self.send("subscribe", endpoint)
+    }
+
+    pub fn unsubscribe(&self, endpoint: &Endpoint) -> Result<()> {
+        self.send("unsubscribe", endpoint)
+    }
+
+    fn send(&self, command_type: &'static str, endpoint: &Endpoint) -> Result<()> {
+        let (stream, tag) = match endpoint.stream_name() {
+            Some(name) => name,
+            None => bail!(ErrorKind::WebSocket(
+                format!("endpoint {:?} has no stream name", endpoint),
+            )),
+        };
+
+        let command = Command {
+            command_type,
+            stream,
+            tag,
+        };
+
+        let json = serde_json::to_string(&command).chain_err(|| {
+            ErrorKind::WebSocket("could not serialize command".to_string())
+        })?;
+
+        self.commands.unbounded_send(json).map_err(|_| {
+            ErrorKind::WebSocket("connection is closed".to_string()).into()
+        })
+    }
+}
+
+/// Parse a text message received from the WebSocket streaming API.
+pub fn parse_message(text: &str) -> Result<(Endpoint, Event)> {
+    let message: Message = serde_json::from_str(text).chain_err(|| {
+        ErrorKind::Deserialize(text.to_string())
+    })?;
+
+    let endpoint = Endpoint::from_stream_name(&message.stream);
+
+    let event = match message.payload {
+        Some(serde_json::Value::String(ref data)) => Event::parse(&message.event, data)?,
+        Some(ref value) => Event::parse(&message.event, &value.to_string())?,
+        None => Event::parse(&message.event, "")?,
+    };
+
+    Ok((endpoint, event))
+}
+
+/// Compute the expected `Sec-WebSocket-Accept` header value for a key.
+pub fn accept_key(key: &str) -> String {
+    let mut sha = sha1::Sha1::new();
+    sha.update(key.as_bytes());
+    sha.update(ACCEPT_GUID.as_bytes());
+    base64::encode(&sha.digest().bytes())
+}
+
+pub fn encode_frame(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+
+    let len = payload.len();
+    if len < 126 {
+        frame.push(0x80 | len as u8);
+    } else if len <= 0xFFFF {
+        frame.push(0x80 | 126);
+        frame.push((len >> 8) as u8);
+        frame.push(len as u8);
+    } else {
+        frame.push(0x80 | 127);
+        for i in (0..8).rev() {
+            frame.push((len as u64 >> (i * 8)) as u8);
+        }
+    }
+
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Decode a single frame from the start of the buffer, returning the frame
+/// and the number of bytes it occupied, or `None` if the buffer does not yet
+/// contain a complete frame. Frames with reserved bits set or with payloads
+/// larger than `MAX_MESSAGE_SIZE` are rejected.
+pub fn decode_frame(buffer: &[u8]) -> Result<Option<(Frame, usize)>> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+
+    if buffer[0] & 0x70 != 0 {
+        bail!(ErrorKind::WebSocket("reserved bits set in frame".to_string()));
+    }
+
+    let fin = buffer[0] & 0x80 != 0;
+    let opcode = buffer[0] & 0x0F;
+    let masked = buffer[1] & 0x80 != 0;
+
+    let (len, mut offset) = match buffer[1] & 0x7F {
+        126 => {
+            if buffer.len() < 4 {
+                return Ok(None);
+            }
+            ((buffer[2] as u64) << 8 | buffer[3] as u64, 4)
+        }
+        127 => {
+            if buffer.len() < 10 {
+                return Ok(None);
+            }
+            let len = buffer[2..10].iter().fold(0u64, |acc, b| acc << 8 | *b as u64);
+            (len, 10)
+        }
+        len => (len as u64, 2),
+    };
+
+    if len > MAX_MESSAGE_SIZE as u64 {
+        bail!(ErrorKind::WebSocket(format!("frame of {} bytes is too large", len)));
+    }
+
+    let mask = if masked {
+        if buffer.len() < offset + 4 {
+            return Ok(None);
+        }
+        offset += 4;
+        Some([
+            buffer[offset - 4],
+            buffer[offset - 3],
+            buffer[offset - 2],
+            buffer[offset - 1],
+        ])
+    } else {
+        None
+    };
+
+    let end = match (len as usize).checked_add(offset) {
+        Some(end) => end,
+        None => bail!(ErrorKind::WebSocket("frame length overflow".to_string())),
+    };
+    if buffer.len() < end {
+        return Ok(None);
+    }
+
+    let payload = match mask {
+        Some(mask) => {
+            buffer[offset..end]
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b ^ mask[i % 4])
+                .collect()
+        }
+        None => buffer[offset..end].to_vec(),
+    };
+
+    let frame = Frame {
+        fin,
+        opcode,
+        payload,
+    };
+
+    Ok(Some((frame, end)))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Handshake,
+    Open,
+    Closing,
+    Closed,
+}
+
+/// A client WebSocket connection over `io`, yielding text messages.
+/// Commands sent through the matching `Subscriptions` are forwarded to the
+/// server as text messages once the handshake completes.
+#[must_use = "streams do nothing unless polled"]
+pub struct WebSocket<T> {
+    io: T,
+    state: State,
+    key: String,
+    commands: Option<mpsc::UnboundedReceiver<String>>,
+    read_buffer: Vec<u8>,
+    write_buffer: Vec<u8>,
+    message: Vec<u8>,
+    // Whether a fragmented message has been started but not finished
+    fragmented: bool,
+}
+
+impl<T> WebSocket<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    /// Start the opening handshake for `url` over an established connection.
+    /// `headers` are extra request headers as name and value pairs, which
+    /// must not contain control characters.
+    pub fn new(
+        io: T,
+        url: &url::Url,
+        headers: &[(&str, &str)],
+        commands: mpsc::UnboundedReceiver<String>,
+    ) -> Result<Self> {
+        let key_bytes = rand::thread_rng().gen::<[u8; 16]>();
+        let key = base64::encode(&key_bytes);
+
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+            None => url.host_str().unwrap_or("").to_string(),
+        };
+
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\n\
+            Host: {}\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Key: {}\r\n\
+            Sec-WebSocket-Version: 13\r\n",
+            path,
+            host,
+            key
+        );
+
+        for &(name, value) in headers {
+            if name.chars().chain(value.chars()).any(|c| c.is_control()) {
+                bail!(ErrorKind::WebSocket(
+                    format!("invalid value for {} header", name),
+                ));
+            }
+
+            request.push_str(name);
+            request.push_str(": ");
+            request.push_str(value);
+            request.push_str("\r\n");
+        }
+        request.push_str("\r\n");
+
+        Ok(WebSocket {
+            io,
+            state: State::Handshake,
+            key,
+            commands: Some(commands),
+            read_buffer: Vec::new(),
+            write_buffer: request.into_bytes(),
+            message: Vec::new(),
+            fragmented: false,
+        })
+    }
+
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) {
+        let mask = rand::thread_rng().gen::<[u8; 4]>();
+        let frame = encode_frame(opcode, payload, mask);
+        self.write_buffer.extend_from_slice(&frame);
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        while !self.write_buffer.is_empty() {
+            match self.io.write(&self.write_buffer) {
+                Ok(0) => bail!(ErrorKind::WebSocket("connection closed".to_string())),
+                Ok(n) => {
+                    self.write_buffer.drain(..n);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e).chain_err(|| ErrorKind::Io),
+            }
+        }
+
+        match self.io.flush() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            other => other.chain_err(|| ErrorKind::Io),
+        }
+    }
+
+    fn forward_commands(&mut self) {
+        let mut commands = Vec::new();
+        let mut finished = false;
+
+        if let Some(ref mut receiver) = self.commands {
+            loop {
+                match receiver.poll() {
+                    Ok(Async::Ready(Some(command))) => commands.push(command),
+                    Ok(Async::Ready(None)) | Err(()) => {
+                        finished = true;
+                        break;
+                    }
+                    Ok(Async::NotReady) => break,
+                }
+            }
+        }
+
+        if finished {
+            self.commands = None;
+        }
+
+        for command in commands {
+            self.send_frame(OPCODE_TEXT, command.as_bytes());
+        }
+    }
+
+    // Returns `true` if the handshake response has been fully received
+    fn read_handshake(&mut self) -> Result<bool> {
+        let end = match self.read_buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(index) => index + 4,
+            None => return Ok(false),
+        };
+
+        let response = String::from_utf8_lossy(&self.read_buffer[..end]).into_owned();
+        self.read_buffer.drain(..end);
+
+        let mut lines = response.split("\r\n");
+        let status_line = lines.next().unwrap_or("");
+        if status_line.split(' ').nth(1) != Some("101") {
+            bail!(ErrorKind::WebSocket(
+                format!("unexpected handshake response: `{}`", status_line),
+            ));
+        }
+
+        let expected = accept_key(&self.key);
+        let accepted = lines.any(|line| {
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts.next().unwrap_or("").trim();
+            name.eq_ignore_ascii_case("sec-websocket-accept") && value == expected
+        });
+
+        if !accepted {
+            bail!(ErrorKind::WebSocket(
+                "invalid Sec-WebSocket-Accept header".to_string(),
+            ));
+        }
+
+        Ok(true)
+    }
+
+    // Returns a complete text message, if one is available
+    fn read_frames(&mut self) -> Result<Option<String>> {
+        while let Some((frame, len)) = decode_frame(&self.read_buffer)? {
+            self.read_buffer.drain(..len);
+
+            match frame.opcode {
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    let continuation = frame.opcode == OPCODE_CONTINUATION;
+                    if continuation && !self.fragmented {
+                        bail!(ErrorKind::WebSocket(
+                            "continuation frame without a message to continue".to_string(),
+                        ));
+                    }
+                    if !continuation && self.fragmented {
+                        bail!(ErrorKind::WebSocket(
+                            "new message started before the previous one finished".to_string(),
+                        ));
+                    }
+
+                    if self.message.len() + frame.payload.len() > MAX_MESSAGE_SIZE {
+                        bail!(ErrorKind::WebSocket("message is too large".to_string()));
+                    }
+
+                    self.message.extend_from_slice(&frame.payload);
+                    self.fragmented = !frame.fin;
+
+                    if frame.fin {
+                        let message = ::std::mem::take(&mut self.message);
+                        return String::from_utf8(message).map(Some).chain_err(
+                            || ErrorKind::Utf8,
+                        );
+                    }
+                }
+                OPCODE_PING => self.send_frame(OPCODE_PONG, &frame.payload),
+                OPCODE_PONG => {}
+                OPCODE_CLOSE => {
+                    if self.state == State::Open {
+                        self.send_frame(OPCODE_CLOSE, &frame.payload);
+                    }
+                    self.state = State::Closed;
+                    return Ok(None);
+                }
+                other => {
+                    bail!(ErrorKind::WebSocket(format!("unknown opcode {}", other)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Start the closing handshake.
+    pub fn close(&mut self) {
+        if self.state == State::Open {
+            self.send_frame(OPCODE_CLOSE, &[0x03, 0xE8]); // 1000, normal closure
+            self.state = State::Closing;
+        }
+    }
+}
+
+impl<T> Stream for WebSocket<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Item = String;
+    type Error = Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Self::Item>>> {
+        loop {
+            if self.state == State::Open {
+                self.forward_commands();
+            }
+
+            self.flush()?;
+
+            match self.state {
+                State::Handshake => {
+                    if self.read_handshake()? {
+                        self.state = State::Open;
+                        continue;
+                    }
+                }
+                State::Open | State::Closing => {
+                    if let Some(message) = self.read_frames()? {
+                        return Ok(Async::Ready(Some(message)));
+                    }
+                }
+                State::Closed => {}
+            }
+
+            if self.state == State::Closed {
+                if self.write_buffer.is_empty() {
+                    return Ok(Async::Ready(None));
+                }
+                return Ok(Async::NotReady);
+            }
+
+            let mut chunk = [0; 4096];
+            match self.io.read(&mut chunk) {
+                Ok(0) => bail!(ErrorKind::WebSocket("connection closed".to_string())),
+                Ok(n) => self.read_buffer.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(Async::NotReady);
+                }
+                Err(e) => return Err(e).chain_err(|| ErrorKind::Io),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{self, Future};
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::rc::Rc;
+
+    #[test]
+    fn accept_key_rfc_example() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        for &len in &[0, 125, 126, 65535, 65536] {
+            let payload = vec![b'x'; len];
+            let encoded = encode_frame(OPCODE_TEXT, &payload, [1, 2, 3, 4]);
+
+            assert_eq!(decode_frame(&encoded[..encoded.len() - 1]).unwrap(), None);
+            assert_eq!(
+                decode_frame(&encoded).unwrap(),
+                Some((
+                    Frame {
+                        fin: true,
+                        opcode: OPCODE_TEXT,
+                        payload,
+                    },
+                    encoded.len(),
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn reject_invalid_frames() {
+        let mut reserved = encode_frame(OPCODE_TEXT, b"hi", [0; 4]);
+        reserved[0] |= 0x40;
+        assert!(decode_frame(&reserved).is_err());
+
+        // A 64-bit length near u64::MAX must not overflow
+        let mut huge = vec![0x81, 0xFF];
+        huge.extend_from_slice(&[0xFF; 8]);
+        assert!(decode_frame(&huge).is_err());
+
+        let mut too_large = vec![0x81, 0x7F];
+        too_large.extend_from_slice(&(MAX_MESSAGE_SIZE as u64 + 1).to_be_bytes());
+        assert!(decode_frame(&too_large).is_err());
+    }
+
+    #[test]
+    fn parse_messages() {
+        let delete = r#"{"stream":["hashtag","cats"],"event":"delete","payload":"123"}"#;
+        assert_eq!(
+            parse_message(delete).unwrap(),
+            (
+                Endpoint::Hashtag("cats".to_string()),
+                Event::Delete("123".to_string()),
+            )
+        );
+
+        let no_payload = r#"{"stream":["user"],"event":"filters_changed"}"#;
+        assert_eq!(
+            parse_message(no_payload).unwrap(),
+            (Endpoint::User, Event::FiltersChanged)
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct MockIo {
+        read: Rc<RefCell<Vec<u8>>>,
+        written: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl Read for MockIo {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut read = self.read.borrow_mut();
+            if read.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let n = ::std::cmp::min(buf.len(), read.len());
+            buf[..n].copy_from_slice(&read[..n]);
+            read.drain(..n);
+            Ok(n)
+        }
+    }
+
+    impl Write for MockIo {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for MockIo {}
+
+    impl AsyncWrite for MockIo {
+        fn shutdown(&mut self) -> ::futures::Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    // Server frames are unmasked, so strip the mask bit and key
+    fn server_frame(opcode: u8, fin: bool, payload: &[u8]) -> Vec<u8> {
+        let mut frame = encode_frame(opcode, payload, [0; 4]);
+        if !fin {
+            frame[0] &= 0x7F;
+        }
+        frame[1] &= 0x7F;
+        frame.drain(2..6);
+        frame
+    }
+
+    // Sends the handshake response, returning the client's request
+    fn handshake(io: &MockIo, ws: &mut WebSocket<MockIo>) -> String {
+        assert_eq!(ws.poll().unwrap(), Async::NotReady);
+
+        let request = String::from_utf8(io.written.borrow_mut().split_off(0)).unwrap();
+        let key = request
+            .lines()
+            .find(|line| line.starts_with("Sec-WebSocket-Key: "))
+            .map(|line| line[19..].to_string())
+            .unwrap();
+
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(&key)
+        );
+        io.read.borrow_mut().extend_from_slice(response.as_bytes());
+
+        request
+    }
+
+    #[test]
+    fn websocket_connection() {
+        let io = MockIo::default();
+        let url = url::Url::parse("wss://example.com/api/v1/streaming").unwrap();
+        let (subscriptions, commands) = Subscriptions::new();
+        subscriptions
+            .subscribe(&Endpoint::Hashtag("cats".into()))
+            .unwrap();
+
+        let mut ws = WebSocket::new(io.clone(), &url, &[], commands).unwrap();
+
+        futures::lazy(|| {
+            let request = handshake(&io, &mut ws);
+            assert!(request.starts_with("GET /api/v1/streaming HTTP/1.1\r\nHost: example.com\r\n"));
+
+            let mut read = io.read.borrow_mut();
+            read.extend_from_slice(&server_frame(OPCODE_TEXT, false, b"hel"));
+            read.extend_from_slice(&server_frame(OPCODE_CONTINUATION, true, b"lo"));
+            drop(read);
+
+            assert_eq!(ws.poll().unwrap(), Async::Ready(Some("hello".to_string())));
+
+            let written = io.written.borrow_mut().split_off(0);
+            let (command, _) = decode_frame(&written).unwrap().unwrap();
+            assert_eq!(
+                String::from_utf8(command.payload).unwrap(),
+                r#"{"type":"subscribe","stream":"hashtag","tag":"cats"}"#
+            );
+
+            Ok::<(), ()>(())
+        }).wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn reject_unexpected_continuation() {
+        let io = MockIo::default();
+        let url = url::Url::parse("wss://example.com/api/v1/streaming").unwrap();
+        let (_subscriptions, commands) = Subscriptions::new();
+
+        let mut ws = WebSocket::new(io.clone(), &url, &[], commands).unwrap();
+
+        futures::lazy(|| {
+            handshake(&io, &mut ws);
+            io.read.borrow_mut().extend_from_slice(
+                &server_frame(OPCODE_CONTINUATION, true, b"lo"),
+            );
+
+            assert!(ws.poll().is_err());
+
+            Ok::<(), ()>(())
+        }).wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn reject_header_injection() {
+        let url = url::Url::parse("wss://example.com/api/v1/streaming").unwrap();
+        let (_subscriptions, commands) = Subscriptions::new();
+        let headers = [("Authorization", "Bearer token\r\nX-Injected: 1")];
+
+        assert!(WebSocket::new(MockIo::default(), &url, &headers, commands).is_err());
+    }
+}